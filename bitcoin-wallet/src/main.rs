@@ -4,7 +4,6 @@ use log;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::process;
-use tokio::task::LocalSet;
 
 use bitcoin_wallet::{config::Config, rpc::RPCServer, wallet::WalletInterface};
 
@@ -175,41 +174,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .unwrap()
         .to_string();
 
-    let local = LocalSet::new();
-
-    local
-        .run_until(async move {
-            std::fs::create_dir_all(&wallet_dir).unwrap_or_else(|e| {
-                eprintln!("Failed to create wallet directory: {}", e);
-                process::exit(1);
-            });
-
-            log::info!("Initializing wallet with network: {:?}", network);
-            let wallet_path = wallet_dir.join(wallet_name);
-            log::info!("Using wallet at: {}", wallet_path.display());
-
-            let wallet = match WalletInterface::new(&wallet_path, &node_socket, network).await {
-                Ok(wallet) => wallet,
-                Err(e) => {
-                    eprintln!("Failed to initialize wallet: {}", e);
-                    process::exit(1);
-                }
-            };
-
-            log::info!("Starting RPC server on {}", rpc_addr);
-            let mut rpc_server = RPCServer::new(wallet, &config.rpc);
-            if let Err(e) = rpc_server.start(rpc_addr).await {
-                eprintln!("Failed to start RPC server: {}", e);
-                process::exit(1);
-            }
-
-            log::info!("Wallet is running. Press Ctrl+C to exit");
-            tokio::signal::ctrl_c().await.unwrap();
-
-            log::info!("Shutting down wallet");
-            rpc_server.stop();
-
-            Ok(())
-        })
-        .await
+    std::fs::create_dir_all(&wallet_dir).unwrap_or_else(|e| {
+        eprintln!("Failed to create wallet directory: {}", e);
+        process::exit(1);
+    });
+
+    log::info!("Initializing wallet with network: {:?}", network);
+    let wallet_path = wallet_dir.join(wallet_name);
+    log::info!("Using wallet at: {}", wallet_path.display());
+
+    let wallet = match WalletInterface::new(&wallet_path, &node_socket, network).await {
+        Ok(wallet) => wallet,
+        Err(e) => {
+            eprintln!("Failed to initialize wallet: {}", e);
+            process::exit(1);
+        }
+    };
+
+    log::info!("Starting RPC server on {}", rpc_addr);
+    let mut rpc_server = RPCServer::new(wallet, &config.rpc);
+    if let Err(e) = rpc_server.start(rpc_addr).await {
+        eprintln!("Failed to start RPC server: {}", e);
+        process::exit(1);
+    }
+
+    log::info!("Wallet is running. Press Ctrl+C to exit");
+    tokio::signal::ctrl_c().await.unwrap();
+
+    log::info!("Shutting down wallet");
+    rpc_server.stop();
+
+    Ok(())
 }
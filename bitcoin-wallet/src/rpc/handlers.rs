@@ -4,7 +4,7 @@ use std::sync::Arc;
 use bitcoin::{Address, Amount, Txid};
 use jsonrpc_core::{Error as RpcError, IoHandler, Params, Value};
 use serde_json::json;
-use tokio::task::{self, LocalSet};
+use tokio::task;
 
 use super::error::rpc_error_from_wallet_error;
 use crate::wallet::{CreateWalletOptions, WalletInterface};
@@ -68,17 +68,12 @@ fn register_loadwallet(io: &mut IoHandler, wallet_interface: Arc<WalletInterface
                 .build()
                 .unwrap();
 
-            let local = LocalSet::new();
             rt.block_on(async {
-                local
-                    .run_until(async {
-                        log::debug!(
-                            "Inside async block in thread {:?}",
-                            std::thread::current().id()
-                        );
-                        wallet_interface.load_wallet(&wallet_name).await
-                    })
-                    .await
+                log::debug!(
+                    "Inside async block in thread {:?}",
+                    std::thread::current().id()
+                );
+                wallet_interface.load_wallet(&wallet_name).await
             })
         }) {
             Ok(_) => Ok(json!({
@@ -254,15 +249,10 @@ fn register_rescanblockchain(io: &mut IoHandler, wallet_interface: Arc<WalletInt
                 .build()
                 .unwrap();
 
-            let local = LocalSet::new();
             rt.block_on(async {
-                local
-                    .run_until(async {
-                        log::debug!("Starting blockchain rescan from height {}", start_height);
-                        wallet_interface
-                            .rescan_blockchain(start_height as i32, stop_height.map(|h| h as i32))
-                            .await
-                    })
+                log::debug!("Starting blockchain rescan from height {}", start_height);
+                wallet_interface
+                    .rescan_blockchain(start_height as i32, stop_height.map(|h| h as i32))
                     .await
             })
         }) {
@@ -1,6 +1,5 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::task::LocalSet;
 
 use jsonrpc_core::IoHandler;
 use jsonrpc_http_server::{Server, ServerBuilder};
@@ -39,15 +38,6 @@ impl RPCServer {
 
         self.server = Some(server);
         log::info!("RPC server started");
-        let local = LocalSet::new();
-        local
-            .run_until(async {
-                loop {
-                    tokio::task::yield_now().await;
-                }
-            })
-            .await;
-
         Ok(())
     }
 
@@ -1,8 +1,10 @@
 use bdk_wallet::{KeychainKind, LocalOutput};
 use bitcoin::{Address, Network, Transaction};
+use futures::TryStreamExt;
 use rand::{self, Rng};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::OnceCell;
 
 use super::database::WalletDatabase;
 use super::notification::NotificationProcessor;
@@ -16,6 +18,7 @@ pub struct WalletInterface {
     wallet: Arc<RwLock<Option<Arc<ThreadSafeWallet>>>>,
     database: WalletDatabase,
     node_socket: String,
+    blocktalk: OnceCell<BlockTalk>,
     network: Network,
 }
 
@@ -39,6 +42,7 @@ impl WalletInterface {
             wallet: Arc::new(RwLock::new(None)),
             database,
             node_socket: node_socket.to_string(),
+            blocktalk: OnceCell::new(),
             network,
         });
 
@@ -80,9 +84,13 @@ impl WalletInterface {
     }
 
     async fn get_blocktalk(&self) -> Result<BlockTalk, WalletError> {
-        BlockTalk::init(&self.node_socket)
-            .await
-            .map_err(WalletError::from)
+        // The connection runs on its own thread, so it can be shared by the
+        // short-lived runtimes the RPC handlers create for each call.
+        let blocktalk = self
+            .blocktalk
+            .get_or_try_init(|| BlockTalk::init(&self.node_socket))
+            .await?;
+        Ok(blocktalk.clone())
     }
 
     fn get_current_wallet(&self) -> Result<Arc<ThreadSafeWallet>, WalletError> {
@@ -155,15 +163,16 @@ impl WalletInterface {
             &wallet_tip.height()
         );
 
-        let start_height = wallet_tip.height() as i32 + 1;
+        let start_height = wallet_tip.height() + 1;
 
         log::info!("🔄 Syncing wallet with blockchain");
-        for height in start_height..=tip_height {
-            if let Ok(block) = blocktalk.chain().get_block(&tip_hash, height).await {
-                wallet_guard
-                    .apply_block(&block, height as u32)
-                    .map_err(|e| WalletError::Generic(format!("Failed to apply block: {}", e)))?;
-            }
+        let heights = start_height..=tip_height.max(0) as u32;
+        blocktalk.chain().require_blocks(heights.clone()).await?;
+        let mut blocks = blocktalk.chain().blocks(heights).await?;
+        while let Some((height, block)) = blocks.try_next().await? {
+            wallet_guard
+                .apply_block(&block, height)
+                .map_err(|e| WalletError::Generic(format!("Failed to apply block: {}", e)))?;
         }
 
         log::info!("✅ Wallet sync completed");
@@ -235,8 +244,15 @@ impl WalletInterface {
             stop_height
         );
 
+        if start_height < 0 || stop_height.is_some_and(|height| height < 0) {
+            return Err(WalletError::Generic(format!(
+                "Invalid rescan range {} to {:?}: heights must not be negative",
+                start_height, stop_height
+            )));
+        }
+
         let blocktalk = self.get_blocktalk().await?;
-        let (tip_height, tip_hash) = blocktalk.chain().get_tip().await?;
+        let (tip_height, _) = blocktalk.chain().get_tip().await?;
         log::info!("Current blockchain tip is at height {}", tip_height);
 
         let stop_height = stop_height.unwrap_or(tip_height);
@@ -256,16 +272,13 @@ impl WalletInterface {
         }
 
         // Process blocks in the specified range
-        for height in start_height..=actual_stop_height {
-            if let Ok(block) = blocktalk.chain().get_block(&tip_hash, height as i32).await {
-                wallet_guard
-                    .apply_block(&block, height as u32)
-                    .map_err(|e| {
-                        WalletError::Generic(format!("Failed to apply block during rescan: {}", e))
-                    })?;
-            } else {
-                log::warn!("Failed to retrieve block at height {}", height);
-            }
+        let heights = start_height as u32..=actual_stop_height.max(0) as u32;
+        blocktalk.chain().require_blocks(heights.clone()).await?;
+        let mut blocks = blocktalk.chain().blocks(heights).await?;
+        while let Some((height, block)) = blocks.try_next().await? {
+            wallet_guard.apply_block(&block, height).map_err(|e| {
+                WalletError::Generic(format!("Failed to apply block during rescan: {}", e))
+            })?;
         }
 
         log::info!(
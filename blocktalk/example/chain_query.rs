@@ -1,7 +1,6 @@
 use blocktalk::{BlockTalk, BlockTalkError, ChainInterface, BlockHash};
 use std::path::Path;
 use std::time::Duration;
-use tokio::task::LocalSet;
 
 #[tokio::main]
 async fn main() -> Result<(), BlockTalkError> {
@@ -17,30 +16,25 @@ async fn main() -> Result<(), BlockTalkError> {
         return Ok(());
     }
 
-    let local = LocalSet::new();
-    local
-        .run_until(async {
-            let blocktalk = match connect_to_node(socket_path).await {
-                Some(bt) => bt,
-                None => return Ok(()),
-            };
+    let blocktalk = match connect_to_node(socket_path).await {
+        Some(bt) => bt,
+        None => return Ok(()),
+    };
 
-            let chain = blocktalk.chain();
+    let chain = blocktalk.chain();
 
-            // Execute chain queries
-            let tip_info = query_chain_tip(chain.as_ref()).await;
-            
-            // If we got the tip info, try to get a block from a few blocks back
-            if let Some((height, tip_hash)) = tip_info {
-                if height > 3 {
-                    // Try to get block from 1 block before tip
-                    get_block_at_height(chain.as_ref(), &tip_hash, height).await;
-                }
-            }
+    // Execute chain queries
+    let tip_info = query_chain_tip(chain.as_ref()).await;
+
+    // If we got the tip info, try to get a block from a few blocks back
+    if let Some((height, tip_hash)) = tip_info {
+        if height > 3 {
+            // Try to get block from 1 block before tip
+            get_block_at_height(chain.as_ref(), &tip_hash, height).await;
+        }
+    }
 
-            Ok(())
-        })
-        .await
+    Ok(())
 }
 
 /// Checks if the socket path exists and prints helpful error if not
@@ -3,7 +3,6 @@ use bitcoin::{Transaction, Txid};
 use std::path::Path;
 use std::time::Duration;
 use std::str::FromStr;
-use tokio::task::LocalSet;
 
 #[tokio::main]
 async fn main() -> Result<(), BlockTalkError> {
@@ -22,28 +21,23 @@ async fn main() -> Result<(), BlockTalkError> {
         return Ok(());
     }
 
-    let local = LocalSet::new();
-    local
-        .run_until(async {
-            let blocktalk = match connect_to_node(socket_path).await {
-                Some(bt) => bt,
-                None => return Ok(()),
-            };
+    let blocktalk = match connect_to_node(socket_path).await {
+        Some(bt) => bt,
+        None => return Ok(()),
+    };
 
-            let mempool = blocktalk.mempool();
+    let mempool = blocktalk.mempool();
 
-            // Check if transaction is in mempool
-            check_transaction_in_mempool(mempool.as_ref(), &txid).await;
+    // Check if transaction is in mempool
+    check_transaction_in_mempool(mempool.as_ref(), &txid).await;
 
-            // Check for descendants
-            check_transaction_descendants(mempool.as_ref(), &txid).await;
+    // Check for descendants
+    check_transaction_descendants(mempool.as_ref(), &txid).await;
 
-            // Get transaction ancestry
-            get_transaction_ancestry(mempool.as_ref(), &txid).await;
+    // Get transaction ancestry
+    get_transaction_ancestry(mempool.as_ref(), &txid).await;
 
-            Ok(())
-        })
-        .await
+    Ok(())
 }
 
 /// Checks if the socket path exists and prints helpful error if not
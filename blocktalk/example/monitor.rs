@@ -5,7 +5,6 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 use std::path::Path;
 use std::time::Duration;
-use tokio::task::LocalSet;
 
 struct BlockMonitor {
     latest_height: Arc<Mutex<i32>>,
@@ -18,15 +17,21 @@ impl NotificationHandler for BlockMonitor {
         notification: ChainNotification,
     ) -> Result<(), BlockTalkError> {
         match notification {
-            ChainNotification::UpdatedBlockTip(_) => {
+            ChainNotification::UpdatedBlockTip {
+                hash,
+                height,
+                initial_block_download,
+            } => {
                 println!("\n╔═══════════════════════╗");
                 println!("║   Block Tip Updated   ║");
                 println!("╚═══════════════════════╝");
+                println!("Height: {}, Hash: {}, IBD: {}", height, hash, initial_block_download);
             }
 
-            ChainNotification::BlockConnected(block) => {
+            ChainNotification::BlockConnected { block: info, .. } => {
                 let mut height = self.latest_height.lock().await;
-                *height += 1;
+                *height = info.height;
+                let block = info.block;
 
                 println!("\n╔════════════════════════════════════════════════════════════════════════════════╗");
                 println!("║                                New Block Connected                             ║");
@@ -52,9 +57,10 @@ impl NotificationHandler for BlockMonitor {
                 println!("╚══════════════╧═══════════════════════════════════════════════════════════════╝");
             }
             
-            ChainNotification::BlockDisconnected(hash) => {
+            ChainNotification::BlockDisconnected { block: info } => {
                 let mut height = self.latest_height.lock().await;
-                *height -= 1;
+                *height = info.height - 1;
+                let hash = info.hash;
                 println!("\n╔════════════════════════════════════════════════════════════════════════╗");
                 println!("║                          Block Disconnected                            ║");
                 println!("╠════════════════════════════════════════════════════════════════════════╣");
@@ -63,11 +69,12 @@ impl NotificationHandler for BlockMonitor {
                 println!("╚══════════════╧══════════════════════════════════════════════════════════╝");
             }
             
-            ChainNotification::TransactionRemovedFromMempool(txid) => {
+            ChainNotification::TransactionRemovedFromMempool { tx, reason } => {
                 println!("\n╔════════════════════════════════════════════════════════════════════════╗");
                 println!("║                    Transaction Removed from Mempool                    ║");
                 println!("╠════════════════════════════════════════════════════════════════════════╣");
-                println!("║ TXID         │ {:<60} ║", txid);
+                println!("║ TXID         │ {:<60} ║", tx.compute_txid());
+                println!("║ Reason       │ {:<60} ║", format!("{:?}", reason));
                 println!("╚══════════════╧══════════════════════════════════════════════════════════╝");
             }
             
@@ -128,33 +135,28 @@ async fn main() -> Result<(), BlockTalkError> {
         return Ok(());
     }
 
-    let local = LocalSet::new();
-    local
-        .run_until(async {
-            let blocktalk = match connect_to_node(socket_path).await {
-                Some(bt) => bt,
-                None => return Ok(()),
-            };
+    let blocktalk = match connect_to_node(socket_path).await {
+        Some(bt) => bt,
+        None => return Ok(()),
+    };
 
-            let chain = blocktalk.chain();
+    let chain = blocktalk.chain();
 
-            // Create and register notification handler
-            let handler = Arc::new(BlockMonitor {
-                latest_height: Arc::new(Mutex::new(0)),
-            });
-            chain.add_notification_handler(handler.clone()).await?;
+    // Create and register notification handler
+    let handler = Arc::new(BlockMonitor {
+        latest_height: Arc::new(Mutex::new(0)),
+    });
+    chain.add_notification_handler(handler.clone()).await?;
 
-            // Start receiving chain updates
-            chain.begin_chain_updates().await?;
+    // Start receiving chain updates
+    chain.begin_chain_updates().await?;
 
-            println!("Monitoring chain updates. Press Ctrl+C to stop.");
-            
-            // Keep the program running
-            tokio::signal::ctrl_c().await?;
-            println!("\nStopping chain updates...");
-            
-            chain.stop_chain_updates().await?;
-            Ok(())
-        })
-        .await
+    println!("Monitoring chain updates. Press Ctrl+C to stop.");
+
+    // Keep the program running
+    tokio::signal::ctrl_c().await?;
+    println!("\nStopping chain updates...");
+
+    chain.stop_chain_updates().await?;
+    Ok(())
 }
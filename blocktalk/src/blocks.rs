@@ -0,0 +1,307 @@
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::VarInt;
+use bitcoin::consensus::Decodable;
+use bitcoin::{Block, BlockHash};
+use futures::{Stream, StreamExt};
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::connection::with_thread;
+use crate::error::ChainErrorKind;
+use crate::status::{Node, NodeStatusInterface};
+use crate::{BlockTalkError, Connection};
+
+/// Number of block requests kept in flight
+const PIPELINE_DEPTH: usize = 8;
+
+type Item = Result<(u32, Block), BlockTalkError>;
+
+/// Blocks of a height range, in order, as a `futures::Stream`.
+///
+/// Created by `ChainInterface::blocks` and `BlockFilterInterface::scan_range`.
+/// Every block is looked up as an
+/// ancestor of the tip at creation time, so the range is consistent even if
+/// new blocks arrive. If that tip stops being part of the active chain, the
+/// stream yields a `ChainErrorKind::Reorganized` error for the first block
+/// that was reorged out and ends.
+pub struct BlockStream {
+    tip: BlockHash,
+    blocks: Pin<Box<dyn Stream<Item = Item> + Send>>,
+    done: bool,
+}
+
+impl BlockStream {
+    pub(crate) fn new(
+        connection: Arc<Connection>,
+        tip: BlockHash,
+        range: RangeInclusive<u32>,
+    ) -> Self {
+        Self::from_heights(connection, tip, futures::stream::iter(range).map(Ok))
+    }
+
+    /// Fetch the blocks at the heights yielded by `heights`, which must be
+    /// ascending. An error from `heights` ends the stream.
+    pub(crate) fn from_heights(
+        connection: Arc<Connection>,
+        tip: BlockHash,
+        heights: impl Stream<Item = Result<u32, BlockTalkError>> + Send + 'static,
+    ) -> Self {
+        let blocks = heights
+            .map(move |height| {
+                let connection = connection.clone();
+                async move { fetch_block(connection, tip, height?).await }
+            })
+            .buffered(PIPELINE_DEPTH);
+        Self {
+            tip,
+            blocks: Box::pin(blocks),
+            done: false,
+        }
+    }
+
+    /// Tip the range is anchored to
+    pub fn tip(&self) -> BlockHash {
+        self.tip
+    }
+}
+
+impl Stream for BlockStream {
+    type Item = Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let item = self.blocks.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Err(_))) = item {
+            self.done = true;
+        }
+        item
+    }
+}
+
+async fn fetch_block(connection: Arc<Connection>, tip: BlockHash, height: u32) -> Item {
+    let ancestor_height = i32::try_from(height).map_err(|_| {
+        BlockTalkError::chain_error(
+            ChainErrorKind::InvalidHeight,
+            format!("Height {} is out of range", height),
+        )
+    })?;
+
+    let (found, in_active_chain, data) = connection
+        .run(move |clients| async move {
+            let mut find_req = with_thread!(
+                clients.chain.find_ancestor_by_height_request(),
+                clients.thread
+            );
+
+            let mut params = find_req.get();
+            params.set_block_hash(tip.as_ref());
+            params.set_ancestor_height(ancestor_height);
+            let mut ancestor = params.get_ancestor().map_err(|e| {
+                log::error!(
+                    "Failed to set ancestor parameters at height {}: {}",
+                    height,
+                    e
+                );
+                BlockTalkError::chain_error(ChainErrorKind::InvalidAncestor, e.to_string())
+            })?;
+            ancestor.set_want_data(true);
+            ancestor.set_want_in_active_chain(true);
+
+            let response = find_req.send().promise.await.map_err(|e| {
+                log::error!("Failed to fetch block at height {}: {}", height, e);
+                BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+            })?;
+
+            let response = response.get()?;
+            let ancestor = response.get_ancestor()?;
+            Ok((
+                response.get_result(),
+                ancestor.get_in_active_chain() != 0,
+                ancestor.get_data()?.to_vec(),
+            ))
+        })
+        .await?;
+
+    if !found {
+        return Err(BlockTalkError::chain_error(
+            ChainErrorKind::BlockNotFound,
+            format!("No block at height {} below {}", height, tip),
+        ));
+    }
+    if !in_active_chain {
+        log::warn!("Block at height {} below {} was reorged out", height, tip);
+        return Err(BlockTalkError::chain_error(
+            ChainErrorKind::Reorganized,
+            format!(
+                "Block at height {} below {} is no longer in the active chain",
+                height, tip
+            ),
+        ));
+    }
+
+    match decode_block(&data)? {
+        Some(block) => Ok((height, block)),
+        None => Err(missing_data_error(
+            &connection,
+            ancestor_height,
+            format!("Block at height {} below {}", height, tip),
+        )
+        .await),
+    }
+}
+
+/// Check that `heights` ends at or below the tip at `tip_height`
+pub(crate) fn check_below_tip(
+    heights: &RangeInclusive<u32>,
+    tip_height: i32,
+) -> Result<(), BlockTalkError> {
+    if !heights.is_empty() && i64::from(*heights.end()) > i64::from(tip_height) {
+        return Err(BlockTalkError::chain_error(
+            ChainErrorKind::InvalidHeight,
+            format!(
+                "Height {} is above the tip at height {}",
+                heights.end(),
+                tip_height
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Decode block data returned by the node. Core sends an empty or null
+/// block when it can't read the block from disk, which maps to `None`
+pub(crate) fn decode_block(data: &[u8]) -> Result<Option<Block>, BlockTalkError> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+    let block = Block::consensus_decode(&mut &data[..]).map_err(|e| {
+        log::error!("Failed to decode block: {}", e);
+        BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+    })?;
+    // Every valid block has a coinbase
+    Ok((!block.txdata.is_empty()).then_some(block))
+}
+
+/// Decode only the header of block data returned by the node, skipping the
+/// transactions. Maps Core's empty or null block to `None` like `decode_block`
+pub(crate) fn decode_header(data: &[u8]) -> Result<Option<Header>, BlockTalkError> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+    let mut reader = data;
+    let (header, tx_count) = Header::consensus_decode(&mut reader)
+        .and_then(|header| Ok((header, VarInt::consensus_decode(&mut reader)?)))
+        .map_err(|e| {
+            log::error!("Failed to decode block header: {}", e);
+            BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+        })?;
+    Ok((tx_count.0 > 0).then_some(header))
+}
+
+pub(crate) fn bytes_to_block_hash(bytes: &[u8]) -> Result<BlockHash, BlockTalkError> {
+    BlockHash::from_slice(bytes).map_err(|_| {
+        log::error!("Invalid hash length: expected 32, got {}", bytes.len());
+        BlockTalkError::chain_error(
+            ChainErrorKind::InvalidBlockData,
+            format!("Invalid hash length: expected 32, got {}", bytes.len()),
+        )
+    })
+}
+
+/// Error for a block the node knows but returned no data for:
+/// `BlockPruned` if the active chain block at `height` is not on disk,
+/// `BlockNotFound` otherwise
+pub(crate) async fn missing_data_error(
+    connection: &Arc<Connection>,
+    height: i32,
+    description: String,
+) -> BlockTalkError {
+    let node = Node::new(connection.clone());
+    let availability = futures::try_join!(node.have_block_on_disk(height), node.prune_height());
+
+    match availability {
+        Ok((false, prune_height)) => {
+            log::warn!("{} has been pruned", description);
+            let message = match prune_height {
+                Some(prune_height) => format!(
+                    "{}: block data has been pruned up to height {}",
+                    description, prune_height
+                ),
+                None => format!("{}: block data is not on disk", description),
+            };
+            BlockTalkError::chain_error(ChainErrorKind::BlockPruned, message)
+        }
+        Ok((true, _)) => BlockTalkError::chain_error(
+            ChainErrorKind::BlockNotFound,
+            format!("{}: block data could not be read", description),
+        ),
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::constants::genesis_block;
+    use bitcoin::Network;
+
+    #[test]
+    fn test_decode_block() {
+        let genesis = genesis_block(Network::Regtest);
+        let data = bitcoin::consensus::serialize(&genesis);
+        assert_eq!(decode_block(&data).unwrap(), Some(genesis.clone()));
+
+        // Core's null block, sent when the block can't be read from disk
+        let null_block = Block {
+            header: genesis.header,
+            txdata: Vec::new(),
+        };
+        let data = bitcoin::consensus::serialize(&null_block);
+        assert_eq!(decode_block(&data).unwrap(), None);
+
+        assert_eq!(decode_block(&[]).unwrap(), None);
+        assert!(decode_block(&[0x01, 0x02]).is_err());
+    }
+
+    #[test]
+    fn test_decode_header() {
+        let genesis = genesis_block(Network::Regtest);
+        let data = bitcoin::consensus::serialize(&genesis);
+        assert_eq!(decode_header(&data).unwrap(), Some(genesis.header));
+        // The transactions are never decoded
+        assert_eq!(decode_header(&data[..81]).unwrap(), Some(genesis.header));
+
+        let null_block = Block {
+            header: genesis.header,
+            txdata: Vec::new(),
+        };
+        let data = bitcoin::consensus::serialize(&null_block);
+        assert_eq!(decode_header(&data).unwrap(), None);
+
+        assert_eq!(decode_header(&[]).unwrap(), None);
+        assert!(decode_header(&[0x01, 0x02]).is_err());
+    }
+
+    #[test]
+    fn test_check_below_tip() {
+        assert!(check_below_tip(&(0..=100), 100).is_ok());
+        assert!(check_below_tip(&(50..=50), 100).is_ok());
+        // An empty range is never above the tip
+        #[allow(clippy::reversed_empty_ranges)]
+        let empty = 200..=150;
+        assert!(check_below_tip(&empty, 100).is_ok());
+        assert!(matches!(
+            check_below_tip(&(0..=101), 100),
+            Err(BlockTalkError::Chain {
+                kind: ChainErrorKind::InvalidHeight,
+                ..
+            })
+        ));
+        // No blocks at all before the genesis block is loaded
+        assert!(check_below_tip(&(0..=0), -1).is_err());
+    }
+}
@@ -1,23 +1,35 @@
-use bitcoin::consensus::Decodable;
 use bitcoin::hashes::Hash;
-use bitcoin::{Block, BlockHash};
+use bitcoin::{Block, BlockHash, OutPoint};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use crate::blocks::{
+    bytes_to_block_hash, check_below_tip, decode_block, decode_header, missing_data_error,
+    BlockStream,
+};
+use crate::coin::{find_coins, Coin};
+use crate::connection::with_thread;
 use crate::error::ChainErrorKind;
+use crate::handler_capnp::handler::Client as HandlerClient;
+use crate::locator::BlockLocator;
+use crate::notification::{DispatchOptions, HandlerId, HandlerMetrics};
+use crate::query::{BlockMeta, BlockQuery};
+use crate::status::{Node, NodeStatusInterface};
+use crate::stream::{NotificationStream, SubscribeOptions};
 use crate::{
-    chain_capnp::chain::Client as ChainClient,
-    notification::{ChainNotificationHandler, NotificationHandler},
-    proxy_capnp::thread::Client as ThreadClient,
-    BlockTalkError, Connection,
+    notification::{ChainNotificationHandler, NotificationHandler, NotificationServer},
+    BlockTalkError, Connection, RpcClients,
 };
 
-#[async_trait::async_trait(?Send)]
-pub trait ChainInterface {
+#[async_trait::async_trait]
+pub trait ChainInterface: Send + Sync {
     /// Get the current tip block's height and hash
     async fn get_tip(&self) -> Result<(i32, BlockHash), BlockTalkError>;
 
     /// Get a block at a specific height
+    /// Fails with `ChainErrorKind::BlockPruned` if the block's data was pruned
     async fn get_block(
         &self,
         node_tip_hash: &bitcoin::BlockHash,
@@ -35,80 +47,163 @@ pub trait ChainInterface {
     ) -> Result<Option<BlockHash>, BlockTalkError>;
 
     /// Get a full block by its hash
+    /// Returns `None` for unknown blocks and fails with
+    /// `ChainErrorKind::BlockPruned` if the block's data was pruned
     async fn get_block_by_hash(
         &self,
         block_hash: &BlockHash,
     ) -> Result<Option<Block>, BlockTalkError>;
 
+    /// Look up the fields selected by `query` for a block
+    /// Returns `None` if the node doesn't know the block
+    async fn query_block(
+        &self,
+        block_hash: &BlockHash,
+        query: &BlockQuery,
+    ) -> Result<Option<BlockMeta>, BlockTalkError>;
+
+    /// Look up the fields selected by `query` for the ancestor of `tip_hash`
+    /// at `height`. Returns `None` if there is no such block
+    async fn query_ancestor(
+        &self,
+        tip_hash: &BlockHash,
+        height: i32,
+        query: &BlockQuery,
+    ) -> Result<Option<BlockMeta>, BlockTalkError>;
+
+    /// First block in the active chain with a timestamp of at least
+    /// `min_time` (UNIX seconds) and a height of at least `min_height`
+    /// Returns its hash, height and time, or `None` if no block matches
+    async fn find_first_block_after(
+        &self,
+        min_time: i64,
+        min_height: i32,
+    ) -> Result<Option<BlockMeta>, BlockTalkError>;
+
+    /// Locator for the current tip
+    async fn get_tip_locator(&self) -> Result<BlockLocator, BlockTalkError>;
+
+    /// Locator for `block_hash`, empty if it is not in the active chain
+    async fn get_active_chain_locator(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<BlockLocator, BlockTalkError>;
+
+    /// Height of the last block of `locator` in the active chain, i.e. where
+    /// a chain described by a saved locator forked off. `None` if none of its
+    /// blocks are in the active chain
+    async fn find_locator_fork(
+        &self,
+        locator: &BlockLocator,
+    ) -> Result<Option<i32>, BlockTalkError>;
+
+    /// Get a block header by its hash
+    /// The node has no header-only lookup, so the block data is transferred,
+    /// but only the header is decoded
+    async fn get_header(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<Option<bitcoin::block::Header>, BlockTalkError>;
+
+    /// Stream the blocks of `heights` in order, anchored to the current tip
+    /// Several blocks are requested at once; the stream fails with
+    /// `ChainErrorKind::Reorganized` if the tip is reorged out meanwhile
+    async fn blocks(&self, heights: RangeInclusive<u32>) -> Result<BlockStream, BlockTalkError>;
+
+    /// Check that the data of every block in `heights` is on disk, e.g.
+    /// before a rescan. Fails with `ChainErrorKind::BlockPruned` otherwise
+    async fn require_blocks(&self, heights: RangeInclusive<u32>) -> Result<(), BlockTalkError>;
+
+    /// Look up outpoints in the node's UTXO set
+    /// Outpoints that are spent or unknown map to `None`
+    async fn find_coins(
+        &self,
+        outpoints: &[OutPoint],
+    ) -> Result<HashMap<OutPoint, Option<Coin>>, BlockTalkError>;
+
     /// Add a notification handler to receive chain updates
+    /// Returns a token for `remove_notification_handler`
     async fn add_notification_handler(
         &self,
         handler: Arc<dyn NotificationHandler>,
-    ) -> Result<(), BlockTalkError>;
+    ) -> Result<HandlerId, BlockTalkError>;
 
     /// Remove a previously added notification handler
-    async fn remove_notification_handler(
-        &self,
-        handler: Arc<dyn NotificationHandler>,
-    ) -> Result<(), BlockTalkError>;
+    async fn remove_notification_handler(&self, id: HandlerId) -> Result<(), BlockTalkError>;
 
     /// Start receiving chain updates
-    /// This must be called after adding handlers for them to receive updates
+    /// Handlers added before or after this call receive updates. Calling it
+    /// again while updates are active has no effect
     async fn begin_chain_updates(&self) -> Result<(), BlockTalkError>;
 
     /// Stop receiving chain updates
-    /// Handlers will stop receiving updates but remain registered
+    /// Unsubscribes from the node; handlers remain registered and
+    /// `begin_chain_updates` can be called again later
     async fn stop_chain_updates(&self) -> Result<(), BlockTalkError>;
+
+    /// Receive chain updates as a stream, starting updates if needed
+    /// The stream's handler is removed when the stream is dropped
+    async fn subscribe(
+        &self,
+        options: SubscribeOptions,
+    ) -> Result<NotificationStream, BlockTalkError>;
+
+    /// Configure how notifications are dispatched to handlers
+    fn set_dispatch_options(&self, options: DispatchOptions) -> Result<(), BlockTalkError>;
+
+    /// Latency and failure counters of every registered handler
+    fn handler_metrics(&self) -> Result<Vec<(HandlerId, HandlerMetrics)>, BlockTalkError>;
 }
 
 pub struct Blockchain {
-    chain_client: ChainClient,
-    thread: ThreadClient,
+    connection: Arc<Connection>,
     notification_handler: Arc<Mutex<ChainNotificationHandler>>,
+    subscription: tokio::sync::Mutex<Option<Subscription>>,
 }
 
-#[async_trait::async_trait(?Send)]
+/// Active `handleNotifications` subscription.
+struct Subscription {
+    /// Session hook re-subscribing after a reconnect
+    hook: u64,
+    /// Capability id of the node's `Handler`, updated when the hook runs
+    node_handler: Arc<Mutex<u64>>,
+}
+
+#[async_trait::async_trait]
 impl ChainInterface for Blockchain {
     async fn get_tip(&self) -> Result<(i32, BlockHash), BlockTalkError> {
         log::debug!("Fetching current chain tip");
-        let height = {
-            let mut height_req = self.chain_client.get_height_request();
-            height_req
-                .get()
-                .get_context()
-                .map_err(|e| {
-                    log::error!("Failed to get height context: {}", e);
-                    BlockTalkError::Connection(e.to_string())
-                })?
-                .set_thread(self.thread.clone());
-
-            let response = height_req.send().promise.await.map_err(|e| {
-                log::error!("Failed to get chain height: {}", e);
-                BlockTalkError::chain_error(ChainErrorKind::InvalidHeight, e.to_string())
-            })?;
-            response.get()?.get_result()
-        };
-
-        let hash_bytes = {
-            let mut hash_req = self.chain_client.get_block_hash_request();
-            hash_req
-                .get()
-                .get_context()
-                .map_err(|e| {
-                    log::error!("Failed to get block hash context: {}", e);
-                    BlockTalkError::Connection(e.to_string())
-                })?
-                .set_thread(self.thread.clone());
-
-            hash_req.get().set_height(height);
-            let response = hash_req.send().promise.await.map_err(|e| {
-                log::error!("Failed to get block hash at height {}: {}", height, e);
-                BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
-            })?;
-            response.get()?.get_result()?.to_vec()
-        };
-
-        let hash = self.bytes_to_block_hash(&hash_bytes).map_err(|e| {
+        let (height, hash_bytes) = self
+            .connection
+            .run(|clients| async move {
+                let height = {
+                    let height_req =
+                        with_thread!(clients.chain.get_height_request(), clients.thread);
+
+                    let response = height_req.send().promise.await.map_err(|e| {
+                        log::error!("Failed to get chain height: {}", e);
+                        BlockTalkError::chain_error(ChainErrorKind::InvalidHeight, e.to_string())
+                    })?;
+                    response.get()?.get_result()
+                };
+
+                let hash_bytes = {
+                    let mut hash_req =
+                        with_thread!(clients.chain.get_block_hash_request(), clients.thread);
+
+                    hash_req.get().set_height(height);
+                    let response = hash_req.send().promise.await.map_err(|e| {
+                        log::error!("Failed to get block hash at height {}: {}", height, e);
+                        BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+                    })?;
+                    response.get()?.get_result()?.to_vec()
+                };
+
+                Ok((height, hash_bytes))
+            })
+            .await?;
+
+        let hash = bytes_to_block_hash(&hash_bytes).map_err(|e| {
             log::error!("Failed to convert hash bytes to BlockHash: {}", e);
             e
         })?;
@@ -127,67 +222,71 @@ impl ChainInterface for Blockchain {
         height: i32,
     ) -> Result<Block, BlockTalkError> {
         log::debug!("Getting block at height {}", height);
-        let mut find_req = self.chain_client.find_ancestor_by_height_request();
-
-        find_req
-            .get()
-            .get_context()
-            .map_err(|e| {
-                log::error!("Failed to get block context at height {}: {}", height, e);
-                BlockTalkError::Connection(e.to_string())
-            })?
-            .set_thread(self.thread.clone());
-
-        let mut params = find_req.get();
-        params.set_block_hash(node_tip_hash.as_ref());
-        params.set_ancestor_height(height);
-        params
-            .get_ancestor()
-            .map_err(|e| {
-                log::error!(
-                    "Failed to set ancestor parameters at height {}: {}",
-                    height,
-                    e
+        let node_tip_hash = *node_tip_hash;
+        let (found, data) = self
+            .connection
+            .run(move |clients| async move {
+                let mut find_req = with_thread!(
+                    clients.chain.find_ancestor_by_height_request(),
+                    clients.thread
                 );
-                BlockTalkError::chain_error(ChainErrorKind::InvalidAncestor, e.to_string())
-            })?
-            .set_want_data(true);
 
-        let response = find_req.send().promise.await.map_err(|e| {
-            log::error!("Failed to fetch block at height {}: {}", height, e);
-            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
-        })?;
-
-        let mut data = response.get()?.get_ancestor()?.get_data()?;
+                let mut params = find_req.get();
+                params.set_block_hash(node_tip_hash.as_ref());
+                params.set_ancestor_height(height);
+                params
+                    .get_ancestor()
+                    .map_err(|e| {
+                        log::error!(
+                            "Failed to set ancestor parameters at height {}: {}",
+                            height,
+                            e
+                        );
+                        BlockTalkError::chain_error(ChainErrorKind::InvalidAncestor, e.to_string())
+                    })?
+                    .set_want_data(true);
+
+                let response = find_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to fetch block at height {}: {}", height, e);
+                    BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+                })?;
+
+                let response = response.get()?;
+                Ok((
+                    response.get_result(),
+                    response.get_ancestor()?.get_data()?.to_vec(),
+                ))
+            })
+            .await?;
 
-        Block::consensus_decode(&mut data).map_err(|e| {
-            log::error!("Failed to decode block at height {}: {}", height, e);
-            BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
-        })
+        if !found {
+            return Err(BlockTalkError::chain_error(
+                ChainErrorKind::BlockNotFound,
+                format!("No block at height {} below {}", height, node_tip_hash),
+            ));
+        }
+        match decode_block(&data)? {
+            Some(block) => Ok(block),
+            None => Err(missing_data_error(
+                &self.connection,
+                height,
+                format!("Block at height {} below {}", height, node_tip_hash),
+            )
+            .await),
+        }
     }
 
     async fn is_in_best_chain(&self, block_hash: &BlockHash) -> Result<bool, BlockTalkError> {
         log::debug!("Checking if block {} is in best chain", block_hash);
-        let hash_bytes = block_hash.to_raw_hash().to_byte_array();
-
-        let mut find_req = self.chain_client.find_block_request();
-        find_req
-            .get()
-            .get_context()?
-            .set_thread(self.thread.clone());
-        find_req.get().set_hash(&hash_bytes);
-
-        let response = find_req.send().promise.await.map_err(|e| {
-            log::error!("Failed to find block {}: {}", block_hash, e);
-            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
-        })?;
-
-        let block_info = response.get()?.get_block().map_err(|e| {
-            log::error!("Failed to get block info for {}: {}", block_hash, e);
-            BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
-        })?;
-
-        let is_active = block_info.get_in_active_chain() != 0;
+        let query = BlockQuery {
+            in_active_chain: true,
+            ..Default::default()
+        };
+        let is_active = self
+            .query_block(block_hash, &query)
+            .await?
+            .and_then(|meta| meta.in_active_chain)
+            .unwrap_or(false);
 
         log::debug!(
             "Block {} is {} in the active chain",
@@ -214,33 +313,37 @@ impl ChainInterface for Blockchain {
         let hash1_bytes = block1_hash.to_raw_hash().to_byte_array();
         let hash2_bytes = block2_hash.to_raw_hash().to_byte_array();
 
-        let mut find_req = self.chain_client.find_common_ancestor_request();
-        find_req
-            .get()
-            .get_context()
-            .map_err(|e| {
-                log::error!("Failed to get ancestor context: {}", e);
-                BlockTalkError::Connection(e.to_string())
-            })?
-            .set_thread(self.thread.clone());
-
-        {
-            let mut params = find_req.get();
-            params.set_block_hash1(&hash1_bytes);
-            params.set_block_hash2(&hash2_bytes);
-        }
-
-        let response = find_req.send().promise.await.map_err(|e| {
-            log::error!("Failed to find common ancestor: {}", e);
-            BlockTalkError::chain_error(ChainErrorKind::InvalidAncestor, e.to_string())
-        })?;
+        let ancestor_bytes = self
+            .connection
+            .run(move |clients| async move {
+                let mut find_req =
+                    with_thread!(clients.chain.find_common_ancestor_request(), clients.thread);
+
+                {
+                    let mut params = find_req.get();
+                    params.set_block_hash1(&hash1_bytes);
+                    params.set_block_hash2(&hash2_bytes);
+                    params.init_ancestor().set_want_hash(true);
+                }
+
+                let response = find_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to find common ancestor: {}", e);
+                    BlockTalkError::chain_error(ChainErrorKind::InvalidAncestor, e.to_string())
+                })?;
+
+                let response = response.get()?;
+                if !response.get_result() {
+                    return Ok(Vec::new());
+                }
+                Ok(response.get_ancestor()?.get_hash()?.to_vec())
+            })
+            .await?;
 
-        let ancestor_bytes = response.get()?.get_ancestor()?.get_data()?;
         if ancestor_bytes.is_empty() {
             log::debug!("No common ancestor found");
             Ok(None)
         } else {
-            let ancestor_hash = self.bytes_to_block_hash(ancestor_bytes)?;
+            let ancestor_hash = bytes_to_block_hash(&ancestor_bytes)?;
             log::debug!("Common ancestor found: {}", ancestor_hash);
             Ok(Some(ancestor_hash))
         }
@@ -251,138 +354,520 @@ impl ChainInterface for Blockchain {
         block_hash: &BlockHash,
     ) -> Result<Option<Block>, BlockTalkError> {
         log::debug!("Getting block with hash {}", block_hash);
-        let hash_bytes = block_hash.to_raw_hash().to_byte_array();
+        let query = BlockQuery {
+            height: true,
+            data: true,
+            ..Default::default()
+        };
+        let Some(meta) = self.query_block(block_hash, &query).await? else {
+            log::debug!("Block {} not found", block_hash);
+            return Ok(None);
+        };
 
-        let mut find_req = self.chain_client.find_block_request();
-        find_req
-            .get()
-            .get_context()
-            .map_err(|e| {
-                log::error!("Failed to get block context for hash {}: {}", block_hash, e);
-                BlockTalkError::Connection(e.to_string())
-            })?
-            .set_thread(self.thread.clone());
+        match meta.block {
+            Some(block) => {
+                log::debug!("Successfully retrieved block {}", block_hash);
+                Ok(Some(block))
+            }
+            None => Err(missing_data_error(
+                &self.connection,
+                meta.height.unwrap_or_default(),
+                format!("Block {}", block_hash),
+            )
+            .await),
+        }
+    }
 
-        find_req.get().set_hash(&hash_bytes);
-        let response = find_req.send().promise.await.map_err(|e| {
-            log::error!("Failed to fetch block with hash {}: {}", block_hash, e);
-            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
-        })?;
+    async fn query_block(
+        &self,
+        block_hash: &BlockHash,
+        query: &BlockQuery,
+    ) -> Result<Option<BlockMeta>, BlockTalkError> {
+        log::debug!("Querying block {} for {:?}", block_hash, query);
+        let block_hash = *block_hash;
+        let query = query.clone();
+        self.connection
+            .run(move |clients| async move {
+                let mut find_req = with_thread!(clients.chain.find_block_request(), clients.thread);
+
+                let mut params = find_req.get();
+                params.set_hash(block_hash.as_ref());
+                query.encode(params.init_block());
+
+                let response = find_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to find block {}: {}", block_hash, e);
+                    BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+                })?;
+
+                let response = response.get()?;
+                if !response.get_result() {
+                    return Ok(None);
+                }
+                BlockMeta::decode(&query, response.get_block()?).map(Some)
+            })
+            .await
+    }
 
-        let block_info = response.get()?.get_block()?;
-        if !block_info.has_data() || block_info.get_data()?.is_empty() {
-            log::debug!("No block data found for hash {}", block_hash);
-            return Ok(None);
+    async fn query_ancestor(
+        &self,
+        tip_hash: &BlockHash,
+        height: i32,
+        query: &BlockQuery,
+    ) -> Result<Option<BlockMeta>, BlockTalkError> {
+        log::debug!(
+            "Querying block at height {} below {} for {:?}",
+            height,
+            tip_hash,
+            query
+        );
+        let tip_hash = *tip_hash;
+        let query = query.clone();
+        self.connection
+            .run(move |clients| async move {
+                let mut find_req = with_thread!(
+                    clients.chain.find_ancestor_by_height_request(),
+                    clients.thread
+                );
+
+                let mut params = find_req.get();
+                params.set_block_hash(tip_hash.as_ref());
+                params.set_ancestor_height(height);
+                query.encode(params.init_ancestor());
+
+                let response = find_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to find block at height {}: {}", height, e);
+                    BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+                })?;
+
+                let response = response.get()?;
+                if !response.get_result() {
+                    return Ok(None);
+                }
+                BlockMeta::decode(&query, response.get_ancestor()?).map(Some)
+            })
+            .await
+    }
+
+    async fn find_first_block_after(
+        &self,
+        min_time: i64,
+        min_height: i32,
+    ) -> Result<Option<BlockMeta>, BlockTalkError> {
+        log::debug!(
+            "Finding first block with time >= {} and height >= {}",
+            min_time,
+            min_height
+        );
+        let query = BlockQuery {
+            hash: true,
+            height: true,
+            time: true,
+            ..Default::default()
+        };
+        let found = self
+            .connection
+            .run(move |clients| async move {
+                let mut find_req = with_thread!(
+                    clients
+                        .chain
+                        .find_first_block_with_time_and_height_request(),
+                    clients.thread
+                );
+
+                let mut params = find_req.get();
+                params.set_min_time(min_time);
+                params.set_min_height(min_height);
+                query.encode(params.init_block());
+
+                let response = find_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to find block after time {}: {}", min_time, e);
+                    BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+                })?;
+
+                let response = response.get()?;
+                if !response.get_result() {
+                    return Ok(None);
+                }
+                BlockMeta::decode(&query, response.get_block()?).map(Some)
+            })
+            .await?;
+
+        match &found {
+            Some(meta) => log::debug!("Found block {:?} at height {:?}", meta.hash, meta.height),
+            None => log::debug!("No block found after time {}", min_time),
         }
+        Ok(found)
+    }
 
-        match bitcoin::consensus::deserialize::<Block>(block_info.get_data()?) {
-            Ok(block) => {
-                log::debug!("Successfully retrieved block {}", block_hash);
-                Ok(Some(block))
+    async fn get_tip_locator(&self) -> Result<BlockLocator, BlockTalkError> {
+        log::debug!("Getting tip locator");
+        let data = self
+            .connection
+            .run(|clients| async move {
+                let locator_req =
+                    with_thread!(clients.chain.get_tip_locator_request(), clients.thread);
+
+                let response = locator_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get tip locator: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result()?.to_vec())
+            })
+            .await?;
+
+        BlockLocator::decode(&data)
+    }
+
+    async fn get_active_chain_locator(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<BlockLocator, BlockTalkError> {
+        log::debug!("Getting locator for block {}", block_hash);
+        let block_hash = *block_hash;
+        let data = self
+            .connection
+            .run(move |clients| async move {
+                let mut locator_req = with_thread!(
+                    clients.chain.get_active_chain_locator_request(),
+                    clients.thread
+                );
+
+                locator_req.get().set_block_hash(block_hash.as_ref());
+
+                let response = locator_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get locator for block {}: {}", block_hash, e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result()?.to_vec())
+            })
+            .await?;
+
+        BlockLocator::decode(&data)
+    }
+
+    async fn find_locator_fork(
+        &self,
+        locator: &BlockLocator,
+    ) -> Result<Option<i32>, BlockTalkError> {
+        log::debug!(
+            "Finding fork of locator with {} hashes",
+            locator.hashes.len()
+        );
+        let data = bitcoin::consensus::serialize(locator);
+        let fork = self
+            .connection
+            .run(move |clients| async move {
+                let mut fork_req =
+                    with_thread!(clients.chain.find_locator_fork_request(), clients.thread);
+
+                fork_req.get().set_locator(data.as_slice());
+
+                let response = fork_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to find locator fork: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let result = response.get()?;
+                Ok(result.get_has_result().then(|| result.get_result()))
+            })
+            .await?;
+
+        log::debug!("Locator fork height: {:?}", fork);
+        Ok(fork)
+    }
+
+    async fn get_header(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<Option<bitcoin::block::Header>, BlockTalkError> {
+        log::debug!("Getting header of block {}", block_hash);
+        let block_hash = *block_hash;
+        let found = self
+            .connection
+            .run(move |clients| async move {
+                let mut find_req = with_thread!(clients.chain.find_block_request(), clients.thread);
+
+                let mut params = find_req.get();
+                params.set_hash(block_hash.as_ref());
+                let mut block = params.init_block();
+                block.set_want_height(true);
+                block.set_want_data(true);
+
+                let response = find_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to find block {}: {}", block_hash, e);
+                    BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+                })?;
+
+                let response = response.get()?;
+                if !response.get_result() {
+                    return Ok(None);
+                }
+                let block = response.get_block()?;
+                Ok(Some((
+                    block.get_height(),
+                    decode_header(block.get_data()?)?,
+                )))
+            })
+            .await?;
+
+        match found {
+            None => {
+                log::debug!("Block {} not found", block_hash);
+                Ok(None)
             }
-            Err(e) => {
-                log::error!("Failed to deserialize block {}: {}", block_hash, e);
-                Err(BlockTalkError::chain_error(
-                    ChainErrorKind::DeserializationFailed,
-                    e.to_string(),
-                ))
+            Some((_, Some(header))) => Ok(Some(header)),
+            Some((height, None)) => {
+                Err(
+                    missing_data_error(&self.connection, height, format!("Block {}", block_hash))
+                        .await,
+                )
             }
         }
     }
 
+    async fn blocks(&self, heights: RangeInclusive<u32>) -> Result<BlockStream, BlockTalkError> {
+        let (tip_height, tip) = self.get_tip().await?;
+        log::debug!("Streaming blocks {:?} below tip {}", heights, tip);
+        check_below_tip(&heights, tip_height)?;
+        Ok(BlockStream::new(self.connection.clone(), tip, heights))
+    }
+
+    async fn require_blocks(&self, heights: RangeInclusive<u32>) -> Result<(), BlockTalkError> {
+        if heights.is_empty() {
+            return Ok(());
+        }
+        let (tip_height, tip) = self.get_tip().await?;
+        log::debug!("Checking block data of {:?} below tip {}", heights, tip);
+        check_below_tip(&heights, tip_height)?;
+
+        // Both ends fit in i32 since they are at most the tip height
+        let min_height = *heights.start() as i32;
+        let max_height = *heights.end() as i32;
+        let node = Node::new(self.connection.clone());
+        let (available, prune_height) = futures::try_join!(
+            node.has_blocks(&tip, min_height, Some(max_height)),
+            node.prune_height()
+        )?;
+
+        if available {
+            return Ok(());
+        }
+        log::warn!("Block data of {:?} is not on disk", heights);
+        let message = match prune_height {
+            Some(prune_height) => format!(
+                "Blocks {:?} are not all on disk, blocks are pruned up to height {}",
+                heights, prune_height
+            ),
+            None => format!("Blocks {:?} are not all on disk", heights),
+        };
+        Err(BlockTalkError::chain_error(
+            ChainErrorKind::BlockPruned,
+            message,
+        ))
+    }
+
+    async fn find_coins(
+        &self,
+        outpoints: &[OutPoint],
+    ) -> Result<HashMap<OutPoint, Option<Coin>>, BlockTalkError> {
+        find_coins(&self.connection, outpoints).await
+    }
+
     async fn add_notification_handler(
         &self,
         handler: Arc<dyn NotificationHandler>,
-    ) -> Result<(), BlockTalkError> {
-        let mut notification_handler = self.notification_handler.lock().map_err(|e| {
-            BlockTalkError::Connection(format!(
-                "Failed to acquire lock for notification handler: {}",
-                e
-            ))
-        })?;
+    ) -> Result<HandlerId, BlockTalkError> {
+        let mut notification_handler = self
+            .notification_handler
+            .lock()
+            .map_err(|e| {
+                BlockTalkError::Connection(format!(
+                    "Failed to acquire lock for notification handler: {}",
+                    e
+                ))
+            })?
+            .clone();
         notification_handler.register_handler(handler).await
     }
 
-    async fn remove_notification_handler(
-        &self,
-        handler: Arc<dyn NotificationHandler>,
-    ) -> Result<(), BlockTalkError> {
-        let mut notification_handler = self.notification_handler.lock().map_err(|e| {
-            BlockTalkError::Connection(format!(
-                "Failed to acquire lock for notification handler: {}",
-                e
-            ))
-        })?;
-        // TODO: Implement handler removal in ChainNotificationHandler if possible
+    async fn remove_notification_handler(&self, id: HandlerId) -> Result<(), BlockTalkError> {
+        let notification_handler = self
+            .notification_handler
+            .lock()
+            .map_err(|e| {
+                BlockTalkError::Connection(format!(
+                    "Failed to acquire lock for notification handler: {}",
+                    e
+                ))
+            })?
+            .clone();
+        if !notification_handler.remove_handler(id)? {
+            log::error!("Notification handler {:?} is not registered", id);
+            return Err(BlockTalkError::chain_error(
+                ChainErrorKind::Other("Unknown notification handler".to_string()),
+                format!("Notification handler {:?} is not registered", id),
+            ));
+        }
+        log::debug!("Removed notification handler {:?}", id);
         Ok(())
     }
 
     async fn begin_chain_updates(&self) -> Result<(), BlockTalkError> {
-        log::debug!("Starting chain update notifications");
-        let handler = self.notification_handler.lock().unwrap().clone();
-        let notification_client = capnp_rpc::new_client(handler);
-        let mut handle_req = self.chain_client.handle_notifications_request();
+        let mut subscription = self.subscription.lock().await;
+        if subscription.is_some() {
+            log::debug!("Chain updates already started");
+            return Ok(());
+        }
 
-        handle_req
-            .get()
-            .get_context()
+        log::debug!("Starting chain update notifications");
+        let handler = self
+            .notification_handler
+            .lock()
             .map_err(|e| {
-                log::error!("Failed to get notification context: {}", e);
-                BlockTalkError::Connection(e.to_string())
+                BlockTalkError::Connection(format!(
+                    "Failed to acquire lock for notification handler: {}",
+                    e
+                ))
             })?
-            .set_thread(self.thread.clone());
-
-        handle_req.get().set_notifications(notification_client);
-        handle_req.send().promise.await.map_err(|e| {
-            log::error!("Failed to start chain updates: {}", e);
-            BlockTalkError::Connection(e.to_string())
-        })?;
+            .clone();
+        let dispatcher = handler.clone();
+        let id = self
+            .connection
+            .run(move |clients| handle_notifications(clients, dispatcher))
+            .await?;
+        let node_handler = Arc::new(Mutex::new(id));
+
+        // Re-subscribe whenever a supervised connection restores its session.
+        let restored = node_handler.clone();
+        let hook = self.connection.add_session_hook(move |clients| {
+            let handler = handler.clone();
+            let restored = restored.clone();
+            async move {
+                let id = handle_notifications(clients, handler).await?;
+                *restored.lock().unwrap_or_else(|e| e.into_inner()) = id;
+                Ok(())
+            }
+        });
 
+        *subscription = Some(Subscription { hook, node_handler });
         log::info!("Successfully started chain updates");
         Ok(())
     }
 
     async fn stop_chain_updates(&self) -> Result<(), BlockTalkError> {
-        // TODO: Implement stopping notifications in the Cap'n Proto RPC layer
+        let Some(subscription) = self.subscription.lock().await.take() else {
+            log::debug!("Chain updates are not active");
+            return Ok(());
+        };
+
+        log::debug!("Stopping chain update notifications");
+        self.connection.remove_session_hook(subscription.hook);
+        let id = *subscription
+            .node_handler
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        self.connection
+            .run(move |clients| async move {
+                let Some(handler) = clients.capabilities.remove::<HandlerClient>(id) else {
+                    log::debug!("Notification subscription ended with its session");
+                    return Ok(());
+                };
+
+                let disconnect_req = with_thread!(handler.disconnect_request(), clients.thread);
+                disconnect_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to disconnect notification handler: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let destroy_req = with_thread!(handler.destroy_request(), clients.thread);
+                destroy_req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to destroy notification handler: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+                Ok(())
+            })
+            .await?;
+
+        log::info!("Successfully stopped chain updates");
         Ok(())
     }
+
+    async fn subscribe(
+        &self,
+        options: SubscribeOptions,
+    ) -> Result<NotificationStream, BlockTalkError> {
+        log::debug!("Subscribing to chain updates with {:?}", options);
+        let notification_handler = self
+            .notification_handler
+            .lock()
+            .map_err(|e| {
+                BlockTalkError::Connection(format!(
+                    "Failed to acquire lock for notification handler: {}",
+                    e
+                ))
+            })?
+            .clone();
+        let stream = NotificationStream::register(notification_handler, options).await?;
+        self.begin_chain_updates().await?;
+        Ok(stream)
+    }
+
+    fn set_dispatch_options(&self, options: DispatchOptions) -> Result<(), BlockTalkError> {
+        self.notification_handler
+            .lock()
+            .map_err(|e| {
+                BlockTalkError::Connection(format!(
+                    "Failed to acquire lock for notification handler: {}",
+                    e
+                ))
+            })?
+            .set_dispatch_options(options)
+    }
+
+    fn handler_metrics(&self) -> Result<Vec<(HandlerId, HandlerMetrics)>, BlockTalkError> {
+        self.notification_handler
+            .lock()
+            .map_err(|e| {
+                BlockTalkError::Connection(format!(
+                    "Failed to acquire lock for notification handler: {}",
+                    e
+                ))
+            })?
+            .metrics()
+    }
 }
 
 impl Blockchain {
     pub fn new(connection: Arc<Connection>) -> Self {
         Self {
-            chain_client: connection.chain_client().clone(),
-            thread: connection.thread().clone(),
-            notification_handler: Arc::new(Mutex::new(ChainNotificationHandler::new())),
-        }
-    }
-
-    pub fn from_client(chain_client: ChainClient, thread: ThreadClient) -> Self {
-        Self {
-            chain_client,
-            thread,
+            connection,
             notification_handler: Arc::new(Mutex::new(ChainNotificationHandler::new())),
+            subscription: tokio::sync::Mutex::new(None),
         }
     }
 
     pub fn notification_handler(&self) -> Arc<Mutex<ChainNotificationHandler>> {
         self.notification_handler.clone()
     }
+}
 
-    // Helper method to convert bytes to BlockHash
-    fn bytes_to_block_hash(&self, bytes: &[u8]) -> Result<BlockHash, BlockTalkError> {
-        if bytes.len() != 32 {
-            log::error!("Invalid hash length: expected 32, got {}", bytes.len());
-            return Err(BlockTalkError::chain_error(
-                ChainErrorKind::InvalidBlockData,
-                format!("Invalid hash length: expected 32, got {}", bytes.len()),
-            ));
-        }
-
-        let mut hash_bytes = [0u8; 32];
-        hash_bytes.copy_from_slice(bytes);
-        Ok(BlockHash::from_raw_hash(
-            bitcoin::hashes::Hash::from_byte_array(hash_bytes),
-        ))
-    }
+/// Register `handler` with the node's `handleNotifications` on the given
+/// session. Returns the capability id of the node's `Handler`.
+async fn handle_notifications(
+    clients: RpcClients,
+    handler: ChainNotificationHandler,
+) -> Result<u64, BlockTalkError> {
+    let notification_client =
+        capnp_rpc::new_client(NotificationServer::new(handler, clients.clone()));
+    let mut handle_req = with_thread!(clients.chain.handle_notifications_request(), clients.thread);
+
+    handle_req.get().set_notifications(notification_client);
+    let response = handle_req.send().promise.await.map_err(|e| {
+        log::error!("Failed to start chain updates: {}", e);
+        BlockTalkError::Connection(e.to_string())
+    })?;
+    let node_handler: HandlerClient = response.get()?.get_result()?;
+    Ok(clients.capabilities.insert(node_handler))
 }
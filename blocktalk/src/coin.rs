@@ -0,0 +1,364 @@
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Amount, OutPoint, ScriptBuf, TxOut};
+use std::collections::HashMap;
+
+use crate::connection::with_thread;
+use crate::error::ChainErrorKind;
+use crate::{BlockTalkError, Connection};
+
+/// Scripts longer than this are replaced by `OP_RETURN` when decompressed,
+/// matching Core's `MAX_SCRIPT_SIZE`.
+const MAX_SCRIPT_SIZE: u64 = 10_000;
+
+/// An unspent (or, in undo data, spent) transaction output together with
+/// the height and coinbase flag of the transaction that created it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Coin {
+    pub output: TxOut,
+    pub height: u32,
+    pub is_coinbase: bool,
+}
+
+/// Undo data for a single non-coinbase transaction: the coins spent by its
+/// inputs, in input order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxUndo {
+    pub prevouts: Vec<Coin>,
+}
+
+/// Decode a serialized `CBlockUndo` into one `TxUndo` per non-coinbase
+/// transaction of the block.
+pub(crate) fn decode_block_undo(data: &[u8]) -> Result<Vec<TxUndo>, BlockTalkError> {
+    let mut reader = Reader::new(data);
+    let tx_count = reader.compact_size()?;
+    let mut txs = Vec::new();
+    for _ in 0..tx_count {
+        let coin_count = reader.compact_size()?;
+        let mut prevouts = Vec::new();
+        for _ in 0..coin_count {
+            prevouts.push(reader.undo_coin()?);
+        }
+        txs.push(TxUndo { prevouts });
+    }
+    reader.finish()?;
+    Ok(txs)
+}
+
+/// Decode a coin in Core's `Coin` serialization, as returned by `findCoins`.
+fn decode_coin(data: &[u8]) -> Result<Coin, BlockTalkError> {
+    let mut reader = Reader::new(data);
+    let code = reader.varint()?;
+    let output = reader.compressed_txout()?;
+    reader.finish()?;
+    coin_from_code(code, output)
+}
+
+/// Look up `outpoints` in the node's UTXO set. Spent or unknown outpoints
+/// map to `None`.
+pub(crate) async fn find_coins(
+    connection: &Connection,
+    outpoints: &[OutPoint],
+) -> Result<HashMap<OutPoint, Option<Coin>>, BlockTalkError> {
+    log::debug!("Looking up {} coins", outpoints.len());
+    let keys: Vec<Vec<u8>> = outpoints
+        .iter()
+        .map(bitcoin::consensus::serialize)
+        .collect();
+
+    let found = connection
+        .run(move |clients| async move {
+            let mut coins_req = with_thread!(clients.chain.find_coins_request(), clients.thread);
+
+            let mut coins = coins_req.get().init_coins(keys.len() as u32);
+            for (i, key) in keys.iter().enumerate() {
+                let mut entry = coins.reborrow().get(i as u32);
+                entry.set_key(key.as_slice())?;
+                entry.set_value(&[][..])?;
+            }
+
+            let response = coins_req.send().promise.await.map_err(|e| {
+                log::error!("Failed to find coins: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?;
+
+            let mut found = Vec::new();
+            for entry in response.get()?.get_coins()?.iter() {
+                found.push((entry.get_key()?.to_vec(), entry.get_value()?.to_vec()));
+            }
+            Ok(found)
+        })
+        .await?;
+
+    let mut coins: HashMap<OutPoint, Option<Coin>> =
+        outpoints.iter().map(|outpoint| (*outpoint, None)).collect();
+    for (key, value) in found {
+        let outpoint: OutPoint = bitcoin::consensus::deserialize(&key).map_err(|e| {
+            log::error!("Failed to decode outpoint: {}", e);
+            BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+        })?;
+        // Spent or unknown outpoints come back without coin data
+        if value.is_empty() {
+            continue;
+        }
+        coins.insert(outpoint, Some(decode_coin(&value)?));
+    }
+
+    log::debug!(
+        "Found {} of {} coins",
+        coins.values().filter(|coin| coin.is_some()).count(),
+        outpoints.len()
+    );
+    Ok(coins)
+}
+
+fn coin_from_code(code: u64, output: TxOut) -> Result<Coin, BlockTalkError> {
+    let height = u32::try_from(code >> 1)
+        .map_err(|_| deserialization_error(format!("Coin height out of range: {}", code >> 1)))?;
+    Ok(Coin {
+        output,
+        height,
+        is_coinbase: code & 1 == 1,
+    })
+}
+
+fn deserialization_error(message: String) -> BlockTalkError {
+    log::error!("Failed to decode coin data: {}", message);
+    BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, message)
+}
+
+/// Inverse of Core's `CompressAmount`.
+fn decompress_amount(compressed: u64) -> Result<u64, BlockTalkError> {
+    if compressed == 0 {
+        return Ok(0);
+    }
+    let overflow = || deserialization_error(format!("Compressed amount too large: {}", compressed));
+    let mut x = compressed - 1;
+    let mut e = x % 10;
+    x /= 10;
+    let mut n = if e < 9 {
+        let d = (x % 9) + 1;
+        x /= 9;
+        x.checked_mul(10)
+            .and_then(|n| n.checked_add(d))
+            .ok_or_else(overflow)?
+    } else {
+        x + 1
+    };
+    while e > 0 {
+        n = n.checked_mul(10).ok_or_else(overflow)?;
+        e -= 1;
+    }
+    Ok(n)
+}
+
+/// Rebuild one of the special scripts of Core's `ScriptCompression`.
+fn decompress_script(kind: u64, payload: &[u8]) -> ScriptBuf {
+    let mut script = Vec::new();
+    match kind {
+        0x00 => {
+            script.extend_from_slice(&[0x76, 0xa9, 20]);
+            script.extend_from_slice(payload);
+            script.extend_from_slice(&[0x88, 0xac]);
+        }
+        0x01 => {
+            script.extend_from_slice(&[0xa9, 20]);
+            script.extend_from_slice(payload);
+            script.push(0x87);
+        }
+        0x02 | 0x03 => {
+            script.extend_from_slice(&[33, kind as u8]);
+            script.extend_from_slice(payload);
+            script.push(0xac);
+        }
+        0x04 | 0x05 => {
+            let mut compressed = [0u8; 33];
+            compressed[0] = kind as u8 - 2;
+            compressed[1..].copy_from_slice(payload);
+            // Core leaves the script empty if the key is not on the curve.
+            if let Ok(key) = PublicKey::from_slice(&compressed) {
+                script.push(65);
+                script.extend_from_slice(&key.serialize_uncompressed());
+                script.push(0xac);
+            }
+        }
+        _ => unreachable!("not a special script type: {}", kind),
+    }
+    ScriptBuf::from_bytes(script)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], BlockTalkError> {
+        if self.data.len() < len {
+            return Err(deserialization_error(format!(
+                "Unexpected end of data: needed {} bytes, {} left",
+                len,
+                self.data.len()
+            )));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, BlockTalkError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn finish(&self) -> Result<(), BlockTalkError> {
+        if !self.data.is_empty() {
+            return Err(deserialization_error(format!(
+                "{} trailing bytes",
+                self.data.len()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Bitcoin `CompactSize`
+    fn compact_size(&mut self) -> Result<u64, BlockTalkError> {
+        let size = match self.byte()? {
+            0xfd => u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()) as u64,
+            0xfe => u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()) as u64,
+            0xff => u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()),
+            size => size as u64,
+        };
+        Ok(size)
+    }
+
+    /// Core's MSB base-128 `VARINT`
+    fn varint(&mut self) -> Result<u64, BlockTalkError> {
+        let mut n: u64 = 0;
+        loop {
+            let ch = self.byte()?;
+            if n > (u64::MAX >> 7) {
+                return Err(deserialization_error("VARINT too large".to_string()));
+            }
+            n = (n << 7) | (ch & 0x7f) as u64;
+            if ch & 0x80 == 0 {
+                return Ok(n);
+            }
+            if n == u64::MAX {
+                return Err(deserialization_error("VARINT too large".to_string()));
+            }
+            n += 1;
+        }
+    }
+
+    /// `TxOutCompression`
+    fn compressed_txout(&mut self) -> Result<TxOut, BlockTalkError> {
+        let value = Amount::from_sat(decompress_amount(self.varint()?)?);
+        let size = self.varint()?;
+        let script_pubkey = match size {
+            0x00 | 0x01 => decompress_script(size, self.bytes(20)?),
+            0x02..=0x05 => decompress_script(size, self.bytes(32)?),
+            _ => {
+                let len = size - 6;
+                if len > MAX_SCRIPT_SIZE {
+                    self.bytes(len as usize)?;
+                    ScriptBuf::from_bytes(vec![0x6a])
+                } else {
+                    ScriptBuf::from_bytes(self.bytes(len as usize)?.to_vec())
+                }
+            }
+        };
+        Ok(TxOut {
+            value,
+            script_pubkey,
+        })
+    }
+
+    /// A spent coin in undo data (`TxInUndoFormatter`)
+    fn undo_coin(&mut self) -> Result<Coin, BlockTalkError> {
+        let code = self.varint()?;
+        if code >> 1 > 0 {
+            // Dummy transaction version kept for backwards compatibility
+            self.varint()?;
+        }
+        let output = self.compressed_txout()?;
+        coin_from_code(code, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        let cases: &[(&[u8], u64)] = &[
+            (&[0x00], 0),
+            (&[0x7f], 127),
+            (&[0x80, 0x00], 128),
+            (&[0x80, 0x48], 200),
+            (&[0xff, 0x7f], 16511),
+            (&[0x82, 0xfe, 0x7f], 65535),
+        ];
+        for (data, expected) in cases {
+            let mut reader = Reader::new(data);
+            assert_eq!(reader.varint().unwrap(), *expected);
+            assert!(reader.finish().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_decompress_amount() {
+        assert_eq!(decompress_amount(0).unwrap(), 0);
+        assert_eq!(decompress_amount(1).unwrap(), 1);
+        assert_eq!(decompress_amount(9).unwrap(), 100_000_000);
+        assert_eq!(decompress_amount(50).unwrap(), 5_000_000_000);
+        assert_eq!(decompress_amount(0x7).unwrap(), 1_000_000);
+    }
+
+    #[test]
+    fn test_decompress_amount_overflow() {
+        assert!(decompress_amount(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_decode_block_undo() {
+        let hash = [0x11u8; 20];
+        // One transaction spending one P2PKH coin of 1 BTC created at height 100
+        let mut data = vec![0x01, 0x01, 0x80, 0x48, 0x00, 0x09, 0x00];
+        data.extend_from_slice(&hash);
+
+        let undo = decode_block_undo(&data).unwrap();
+        assert_eq!(undo.len(), 1);
+        assert_eq!(undo[0].prevouts.len(), 1);
+
+        let coin = &undo[0].prevouts[0];
+        assert_eq!(coin.height, 100);
+        assert!(!coin.is_coinbase);
+        assert_eq!(coin.output.value, Amount::from_sat(100_000_000));
+        assert!(coin.output.script_pubkey.is_p2pkh());
+        assert_eq!(&coin.output.script_pubkey.as_bytes()[3..23], &hash);
+    }
+
+    #[test]
+    fn test_decode_coin() {
+        // Coinbase output of 50 BTC at height 1 paying to a 4-byte raw script
+        let data = [0x03, 0x32, 0x0a, 0x51, 0x52, 0x53, 0x54];
+
+        let coin = decode_coin(&data).unwrap();
+        assert_eq!(coin.height, 1);
+        assert!(coin.is_coinbase);
+        assert_eq!(coin.output.value, Amount::from_sat(5_000_000_000));
+        assert_eq!(
+            coin.output.script_pubkey.as_bytes(),
+            &[0x51, 0x52, 0x53, 0x54]
+        );
+    }
+
+    #[test]
+    fn test_decode_block_undo_truncated() {
+        assert!(decode_block_undo(&[0x01, 0x01, 0x80]).is_err());
+        assert!(decode_block_undo(&[0x00, 0x00]).is_err());
+    }
+}
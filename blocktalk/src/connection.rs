@@ -1,13 +1,42 @@
 use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
-use std::sync::Arc;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::task::JoinHandle;
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
 use crate::chain_capnp::chain::Client as ChainClient;
 use crate::init_capnp::init::Client as InitClient;
+use crate::mining_capnp::mining::Client as MiningClient;
 use crate::proxy_capnp::thread::Client as ThreadClient;
+use crate::proxy_capnp::thread_map::Client as ThreadMapClient;
 use crate::BlockTalkError;
 
+/// Set `thread` in the context of a request to the node and return the
+/// request. Returns a `BlockTalkError` from the enclosing function if the
+/// request has no context.
+macro_rules! with_thread {
+    ($request:expr, $thread:expr) => {{
+        let mut req = $request;
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get context for {}: {}", stringify!($request), e);
+                $crate::BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread($thread.clone());
+        req
+    }};
+}
+pub(crate) use with_thread;
+
 #[async_trait::async_trait(?Send)]
 pub trait ConnectionProvider: Send + Sync {
     async fn create_network(
@@ -31,10 +60,7 @@ pub trait ConnectionProvider: Send + Sync {
         tokio::task::spawn_local(rpc)
     }
 
-    async fn create_clients(
-        &self,
-        init: &InitClient,
-    ) -> Result<(ThreadClient, ChainClient), BlockTalkError>;
+    async fn create_clients(&self, init: &InitClient) -> Result<RpcClients, BlockTalkError>;
 }
 
 pub struct UnixConnectionProvider;
@@ -74,10 +100,7 @@ impl ConnectionProvider for UnixConnectionProvider {
         (rpc, init_interface, disconnector)
     }
 
-    async fn create_clients(
-        &self,
-        init: &InitClient,
-    ) -> Result<(ThreadClient, ChainClient), BlockTalkError> {
+    async fn create_clients(&self, init: &InitClient) -> Result<RpcClients, BlockTalkError> {
         // Create thread client
         let mk_init_req = init.construct_request();
         let response = mk_init_req.send().promise.await.map_err(|e| {
@@ -103,14 +126,7 @@ impl ConnectionProvider for UnixConnectionProvider {
         log::debug!("Thread client established");
 
         // Create chain client using the thread
-        let mut mk_chain_req = init.make_chain_request();
-        {
-            let mut context = mk_chain_req.get().get_context().map_err(|e| {
-                log::error!("Failed to get chain context: {}", e);
-                BlockTalkError::Connection(e.to_string())
-            })?;
-            context.set_thread(thread.clone());
-        }
+        let mk_chain_req = with_thread!(init.make_chain_request(), thread);
 
         let response = mk_chain_req.send().promise.await.map_err(|e| {
             log::error!("Failed to initialize chain client: {}", e);
@@ -123,44 +139,381 @@ impl ConnectionProvider for UnixConnectionProvider {
         })?;
         log::debug!("Chain client established");
 
-        Ok((thread, chain_client))
+        // Create mining client using the thread
+        let mk_mining_req = with_thread!(init.make_mining_request(), thread);
+
+        let response = mk_mining_req.send().promise.await.map_err(|e| {
+            log::error!("Failed to initialize mining client: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+
+        let mining_client = response.get()?.get_result().map_err(|e| {
+            log::error!("Failed to get mining client result: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+        log::debug!("Mining client established");
+
+        Ok(RpcClients::new(
+            thread_map,
+            thread,
+            chain_client,
+            mining_client,
+        ))
+    }
+}
+
+/// Capnp clients for a single session with the node.
+///
+/// These are bound to the RPC thread and are only handed to tasks scheduled
+/// through [`Connection::run`].
+#[derive(Clone)]
+pub struct RpcClients {
+    pub thread_map: ThreadMapClient,
+    pub thread: ThreadClient,
+    pub chain: ChainClient,
+    pub mining: MiningClient,
+    pub(crate) capabilities: CapabilityTable,
+}
+
+impl RpcClients {
+    pub fn new(
+        thread_map: ThreadMapClient,
+        thread: ThreadClient,
+        chain: ChainClient,
+        mining: MiningClient,
+    ) -> Self {
+        Self {
+            thread_map,
+            thread,
+            chain,
+            mining,
+            capabilities: CapabilityTable::default(),
+        }
+    }
+
+    /// Create an additional node thread, for calls that would otherwise block
+    /// the shared `thread` for a long time.
+    pub async fn make_thread(&self) -> Result<ThreadClient, BlockTalkError> {
+        let response = self
+            .thread_map
+            .make_thread_request()
+            .send()
+            .promise
+            .await
+            .map_err(|e| {
+                log::error!("Failed to create thread: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?;
+        Ok(response.get()?.get_result()?)
+    }
+}
+
+/// Ids are unique across sessions so a stale id never resolves to a
+/// capability of a newer session.
+static NEXT_CAPABILITY_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Capabilities returned by the node that are referenced by id from outside
+/// the RPC thread, such as block templates. The table belongs to a single
+/// session and is dropped with it.
+#[derive(Clone, Default)]
+pub(crate) struct CapabilityTable {
+    entries: Rc<RefCell<HashMap<u64, Box<dyn Any>>>>,
+}
+
+impl CapabilityTable {
+    pub(crate) fn insert<T: 'static>(&self, capability: T) -> u64 {
+        let id = NEXT_CAPABILITY_ID.fetch_add(1, Ordering::Relaxed);
+        self.entries.borrow_mut().insert(id, Box::new(capability));
+        id
+    }
+
+    pub(crate) fn get<T: Clone + 'static>(&self, id: u64) -> Option<T> {
+        self.entries.borrow().get(&id)?.downcast_ref::<T>().cloned()
+    }
+
+    pub(crate) fn remove<T: 'static>(&self, id: u64) -> Option<T> {
+        let entry = self.entries.borrow_mut().remove(&id)?;
+        entry.downcast::<T>().ok().map(|capability| *capability)
     }
 }
 
+type Task = Box<dyn FnOnce(RpcClients) -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
+type SessionHook = Arc<
+    dyn Fn(RpcClients) -> Pin<Box<dyn Future<Output = Result<(), BlockTalkError>>>> + Send + Sync,
+>;
+
+type SessionHooks = Arc<Mutex<BTreeMap<u64, SessionHook>>>;
+
+enum Request {
+    Call(Task),
+    Shutdown(oneshot::Sender<Result<(), BlockTalkError>>),
+}
+
+/// State of the connection to the node, as observed by the RPC thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// A session is established and calls are forwarded to the node.
+    Connected,
+    /// The session was lost and the given reconnection attempt is pending.
+    /// Calls made in this state fail immediately.
+    Reconnecting { attempt: u32 },
+    /// The connection was closed or reconnection gave up.
+    Disconnected,
+}
+
+/// Exponential backoff used by a supervised connection to re-establish a
+/// lost session.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Delay before the first reconnection attempt.
+    pub initial_delay: Duration,
+    /// Upper bound for the delay between attempts.
+    pub max_delay: Duration,
+    /// Factor applied to the delay after each failed attempt.
+    pub multiplier: f64,
+    /// Number of attempts before giving up, `None` to retry forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay to wait before the given (1-based) attempt.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.max(1.0).powi(exponent);
+        Duration::from_secs_f64(delay.min(self.max_delay.as_secs_f64()))
+    }
+}
+
+/// Handle to a connection with the node.
+///
+/// The capnp `RpcSystem` and all clients live on a dedicated driver thread
+/// running its own single-threaded runtime. The handle itself only holds a
+/// request channel, so it is `Send + Sync` and can be used from any runtime.
 pub struct Connection {
-    rpc_handle: JoinHandle<Result<(), capnp::Error>>,
-    disconnector: capnp_rpc::Disconnector<twoparty::VatId>,
-    thread: ThreadClient,
-    chain_client: ChainClient,
+    requests: mpsc::UnboundedSender<Request>,
+    state: watch::Receiver<ConnectionState>,
+    hooks: SessionHooks,
+    next_hook_id: AtomicU64,
 }
 
 impl Connection {
     pub async fn connect(
         socket_path: &str,
         provider: Box<dyn ConnectionProvider>,
+    ) -> Result<Arc<Self>, BlockTalkError> {
+        Self::spawn(socket_path, provider, None).await
+    }
+
+    pub async fn connect_default(socket_path: &str) -> Result<Arc<Self>, BlockTalkError> {
+        Self::connect(socket_path, Box::new(UnixConnectionProvider)).await
+    }
+
+    /// Connect to the node and keep the connection alive.
+    ///
+    /// When the session is lost the RPC thread re-creates the network and
+    /// clients following `policy`, then re-runs every registered session hook
+    /// so subscriptions survive a node restart. The initial connection is not
+    /// retried.
+    pub async fn connect_supervised(
+        socket_path: &str,
+        provider: Box<dyn ConnectionProvider>,
+        policy: ReconnectPolicy,
+    ) -> Result<Arc<Self>, BlockTalkError> {
+        Self::spawn(socket_path, provider, Some(policy)).await
+    }
+
+    async fn spawn(
+        socket_path: &str,
+        provider: Box<dyn ConnectionProvider>,
+        policy: Option<ReconnectPolicy>,
     ) -> Result<Arc<Self>, BlockTalkError> {
         log::info!("Connecting to Bitcoin node at {}", socket_path);
 
-        let network = provider.create_network(socket_path).await?;
-        let (rpc, init_interface, disconnector) = provider.create_rpc(network);
-        let rpc_handle = provider.spawn_rpc(rpc);
+        let (requests, receiver) = mpsc::unbounded_channel();
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let (state_tx, state) = watch::channel(ConnectionState::Disconnected);
+        let hooks = SessionHooks::default();
+        let driver = Driver {
+            socket_path: socket_path.to_string(),
+            provider,
+            policy,
+            state: state_tx,
+            hooks: hooks.clone(),
+        };
+
+        std::thread::Builder::new()
+            .name("blocktalk-rpc".to_string())
+            .spawn(move || run_driver(driver, receiver, ready_tx))
+            .map_err(|e| {
+                log::error!("Failed to spawn RPC thread: {}", e);
+                BlockTalkError::Io(e.to_string())
+            })?;
 
-        let (thread, chain_client) = provider.create_clients(&init_interface).await?;
+        ready_rx.await.map_err(|_| {
+            log::error!("RPC thread exited before the connection was established");
+            BlockTalkError::Connection("RPC thread exited during connect".to_string())
+        })??;
 
         log::info!("Connection to node established successfully");
         Ok(Arc::new(Self {
-            rpc_handle,
-            disconnector,
-            thread,
-            chain_client,
+            requests,
+            state,
+            hooks,
+            next_hook_id: AtomicU64::new(0),
         }))
     }
 
-    pub async fn connect_default(socket_path: &str) -> Result<Arc<Self>, BlockTalkError> {
-        Self::connect(socket_path, Box::new(UnixConnectionProvider)).await
+    /// Current state of the connection.
+    pub fn state(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+
+    /// Receiver notified on every connection state change.
+    pub fn state_changes(&self) -> watch::Receiver<ConnectionState> {
+        self.state.clone()
+    }
+
+    /// Run `f` on the RPC thread with the current session's clients and wait
+    /// for its result.
+    ///
+    /// The request is queued immediately; the returned future only waits for
+    /// the reply and is `Send`, so it can be awaited from multi-threaded
+    /// runtimes.
+    pub fn run<F, Fut, T>(
+        &self,
+        f: F,
+    ) -> impl Future<Output = Result<T, BlockTalkError>> + Send + 'static
+    where
+        F: FnOnce(RpcClients) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, BlockTalkError>> + 'static,
+        T: Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+        let task: Task = Box::new(move |clients| -> Pin<Box<dyn Future<Output = ()>>> {
+            Box::pin(async move {
+                let _ = reply.send(f(clients).await);
+            })
+        });
+        let queued = self.requests.send(Request::Call(task)).is_ok();
+
+        async move {
+            if !queued {
+                return Err(BlockTalkError::Connection(
+                    "RPC thread is not running".to_string(),
+                ));
+            }
+            response
+                .await
+                .map_err(|_| BlockTalkError::Connection("Not connected to node".to_string()))?
+        }
+    }
+
+    /// Like [`Connection::run`], but does not wait for the result. Used where
+    /// awaiting is not possible, e.g. to release node resources on drop.
+    pub fn run_detached<F, Fut>(&self, f: F)
+    where
+        F: FnOnce(RpcClients) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), BlockTalkError>> + 'static,
+    {
+        let task: Task = Box::new(move |clients| -> Pin<Box<dyn Future<Output = ()>>> {
+            Box::pin(async move {
+                if let Err(e) = f(clients).await {
+                    log::warn!("Detached RPC call failed: {}", e);
+                }
+            })
+        });
+        if self.requests.send(Request::Call(task)).is_err() {
+            log::debug!("RPC thread is not running, dropping detached call");
+        }
+    }
+
+    /// Register a hook that is run on the RPC thread with the new clients
+    /// every time a supervised connection re-establishes its session.
+    ///
+    /// Returns an id for [`Connection::remove_session_hook`].
+    pub fn add_session_hook<F, Fut>(&self, hook: F) -> u64
+    where
+        F: Fn(RpcClients) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), BlockTalkError>> + 'static,
+    {
+        let id = self.next_hook_id.fetch_add(1, Ordering::Relaxed);
+        let hook: SessionHook = Arc::new(
+            move |clients| -> Pin<Box<dyn Future<Output = Result<(), BlockTalkError>>>> {
+                Box::pin(hook(clients))
+            },
+        );
+        self.hooks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, hook);
+        id
+    }
+
+    pub fn remove_session_hook(&self, id: u64) -> bool {
+        self.hooks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&id)
+            .is_some()
+    }
+
+    pub async fn disconnect(&self) -> Result<(), BlockTalkError> {
+        let (reply, response) = oneshot::channel();
+        if self.requests.send(Request::Shutdown(reply)).is_err() {
+            log::debug!("RPC thread already stopped");
+            return Ok(());
+        }
+        response.await.unwrap_or(Ok(()))
+    }
+}
+
+struct Session {
+    rpc_handle: JoinHandle<Result<(), capnp::Error>>,
+    disconnector: capnp_rpc::Disconnector<twoparty::VatId>,
+    clients: RpcClients,
+}
+
+impl Session {
+    async fn open(
+        socket_path: &str,
+        provider: &dyn ConnectionProvider,
+    ) -> Result<Self, BlockTalkError> {
+        let network = provider.create_network(socket_path).await?;
+        let (rpc, init_interface, disconnector) = provider.create_rpc(network);
+        let rpc_handle = provider.spawn_rpc(rpc);
+
+        // The RPC system has to run while the clients are created, so stop it
+        // if that fails instead of leaving it and its socket behind
+        let clients = match provider.create_clients(&init_interface).await {
+            Ok(clients) => clients,
+            Err(e) => {
+                log::error!("Failed to create clients, closing the RPC system: {}", e);
+                drop(disconnector);
+                rpc_handle.abort();
+                return Err(e);
+            }
+        };
+
+        Ok(Self {
+            rpc_handle,
+            disconnector,
+            clients,
+        })
     }
 
-    pub async fn disconnect(self) -> Result<(), BlockTalkError> {
+    async fn close(self) -> Result<(), BlockTalkError> {
         log::info!("Disconnecting from node");
         self.disconnector.await.map_err(|e| {
             log::error!("Failed to disconnect RPC: {}", e);
@@ -183,13 +536,195 @@ impl Connection {
         log::info!("Disconnection completed successfully");
         Ok(())
     }
+}
+
+/// How a session or a reconnection loop ended.
+enum SessionEnd {
+    /// The handle asked to shut down or every handle was dropped.
+    Closed,
+    /// The node went away.
+    Lost,
+}
 
-    pub fn chain_client(&self) -> &ChainClient {
-        &self.chain_client
+struct Driver {
+    socket_path: String,
+    provider: Box<dyn ConnectionProvider>,
+    policy: Option<ReconnectPolicy>,
+    state: watch::Sender<ConnectionState>,
+    hooks: SessionHooks,
+}
+
+impl Driver {
+    /// Forward calls to `session` until it is closed or lost.
+    async fn serve(
+        &self,
+        mut session: Session,
+        requests: &mut mpsc::UnboundedReceiver<Request>,
+    ) -> SessionEnd {
+        loop {
+            tokio::select! {
+                request = requests.recv() => match request {
+                    Some(Request::Call(task)) => {
+                        tokio::task::spawn_local(task(session.clients.clone()));
+                    }
+                    Some(Request::Shutdown(reply)) => {
+                        let _ = reply.send(session.close().await);
+                        return SessionEnd::Closed;
+                    }
+                    None => {
+                        if let Err(e) = session.close().await {
+                            log::warn!("Failed to close connection after all handles were dropped: {}", e);
+                        }
+                        return SessionEnd::Closed;
+                    }
+                },
+                result = &mut session.rpc_handle => {
+                    match result {
+                        Ok(Ok(())) => log::warn!("RPC connection to node closed"),
+                        Ok(Err(e)) => log::error!("RPC connection to node failed: {}", e),
+                        Err(e) => log::error!("RPC task failed: {}", e),
+                    }
+                    return SessionEnd::Lost;
+                }
+            }
+        }
     }
 
-    pub fn thread(&self) -> &ThreadClient {
-        &self.thread
+    /// Re-open the session following `policy`. Calls queued while waiting are
+    /// dropped, which fails them with a connection error.
+    async fn reconnect(
+        &self,
+        policy: &ReconnectPolicy,
+        requests: &mut mpsc::UnboundedReceiver<Request>,
+    ) -> Result<Session, SessionEnd> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            if policy.max_attempts.is_some_and(|max| attempt > max) {
+                log::error!(
+                    "Giving up reconnecting to node after {} attempts",
+                    attempt - 1
+                );
+                return Err(SessionEnd::Lost);
+            }
+            self.state
+                .send_replace(ConnectionState::Reconnecting { attempt });
+
+            let delay = tokio::time::sleep(policy.delay(attempt));
+            tokio::pin!(delay);
+            loop {
+                tokio::select! {
+                    _ = &mut delay => break,
+                    request = requests.recv() => match request {
+                        Some(Request::Call(_)) => {}
+                        Some(Request::Shutdown(reply)) => {
+                            let _ = reply.send(Ok(()));
+                            return Err(SessionEnd::Closed);
+                        }
+                        None => return Err(SessionEnd::Closed),
+                    },
+                }
+            }
+
+            log::info!(
+                "Reconnecting to node at {} (attempt {})",
+                self.socket_path,
+                attempt
+            );
+            match Session::open(&self.socket_path, self.provider.as_ref()).await {
+                Ok(session) => {
+                    self.restore(&session).await;
+                    self.state.send_replace(ConnectionState::Connected);
+                    log::info!("Connection to node re-established");
+                    return Ok(session);
+                }
+                Err(e) => log::warn!("Reconnection attempt {} failed: {}", attempt, e),
+            }
+        }
+    }
+
+    /// Run the session hooks against a freshly opened session.
+    async fn restore(&self, session: &Session) {
+        let hooks: Vec<SessionHook> = self
+            .hooks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect();
+        log::debug!("Restoring {} session hooks", hooks.len());
+        for hook in hooks {
+            if let Err(e) = hook(session.clients.clone()).await {
+                log::error!("Failed to restore session state: {}", e);
+            }
+        }
+    }
+}
+
+fn run_driver(
+    driver: Driver,
+    requests: mpsc::UnboundedReceiver<Request>,
+    ready: oneshot::Sender<Result<(), BlockTalkError>>,
+) {
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            log::error!("Failed to build RPC runtime: {}", e);
+            let _ = ready.send(Err(e.into()));
+            return;
+        }
+    };
+
+    let local = tokio::task::LocalSet::new();
+    local.block_on(&runtime, drive(driver, requests, ready));
+}
+
+async fn drive(
+    driver: Driver,
+    mut requests: mpsc::UnboundedReceiver<Request>,
+    ready: oneshot::Sender<Result<(), BlockTalkError>>,
+) {
+    let mut session = match Session::open(&driver.socket_path, driver.provider.as_ref()).await {
+        Ok(session) => {
+            driver.state.send_replace(ConnectionState::Connected);
+            let _ = ready.send(Ok(()));
+            session
+        }
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    loop {
+        if let SessionEnd::Closed = driver.serve(session, &mut requests).await {
+            driver.state.send_replace(ConnectionState::Disconnected);
+            return;
+        }
+        let Some(policy) = &driver.policy else {
+            break;
+        };
+        session = match driver.reconnect(policy, &mut requests).await {
+            Ok(session) => session,
+            Err(SessionEnd::Closed) => {
+                driver.state.send_replace(ConnectionState::Disconnected);
+                return;
+            }
+            Err(SessionEnd::Lost) => break,
+        };
+    }
+    driver.state.send_replace(ConnectionState::Disconnected);
+
+    // The session is gone; dropping queued calls fails them with a
+    // connection error until the handle is shut down.
+    while let Some(request) = requests.recv().await {
+        if let Request::Shutdown(reply) = request {
+            let _ = reply.send(Ok(()));
+            return;
+        }
     }
 }
 
@@ -270,10 +805,7 @@ mod tests {
             unimplemented!("Mock create_rpc")
         }
 
-        async fn create_clients(
-            &self,
-            _init: &InitClient,
-        ) -> Result<(ThreadClient, ChainClient), BlockTalkError> {
+        async fn create_clients(&self, _init: &InitClient) -> Result<RpcClients, BlockTalkError> {
             match &self.clients_error {
                 Some(error) => Err(error.clone()),
                 None => unimplemented!("Mock create_clients"),
@@ -298,4 +830,20 @@ mod tests {
         let result = Connection::connect("test_path", Box::new(provider)).await;
         assert!(matches!(result, Err(e) if e == error));
     }
+
+    #[test]
+    fn test_reconnect_policy_backoff() {
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            max_attempts: None,
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(4), Duration::from_millis(800));
+        assert_eq!(policy.delay(5), Duration::from_secs(1));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1));
+    }
 }
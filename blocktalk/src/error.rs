@@ -8,6 +8,10 @@ pub enum ChainErrorKind {
     DeserializationFailed,
     InvalidAncestor,
     InvalidBlockData,
+    /// A block is no longer part of the active chain
+    Reorganized,
+    /// Block data has been pruned from disk
+    BlockPruned,
     Other(String),
 }
 
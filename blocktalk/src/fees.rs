@@ -0,0 +1,335 @@
+use bitcoin::FeeRate;
+use std::sync::Arc;
+
+use crate::chain_capnp::{estimator_bucket, fee_calculation};
+use crate::connection::with_thread;
+use crate::error::ChainErrorKind;
+use crate::{BlockTalkError, Connection};
+
+/// Fee estimation mode, matching `estimatesmartfee`'s `estimate_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EstimateMode {
+    /// Consider a longer history of blocks, more likely to be sufficient
+    Conservative,
+    /// Respond faster to short-term drops in fee market demand
+    Economical,
+}
+
+/// Why the estimator returned the fee rate it did, mirroring Core's `FeeReason`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeReason {
+    None,
+    HalfEstimate,
+    FullEstimate,
+    DoubleEstimate,
+    Conservative,
+    MempoolMin,
+    PayTxFee,
+    Fallback,
+    Required,
+}
+
+impl TryFrom<i32> for FeeReason {
+    type Error = BlockTalkError;
+
+    fn try_from(reason: i32) -> Result<Self, Self::Error> {
+        match reason {
+            0 => Ok(Self::None),
+            1 => Ok(Self::HalfEstimate),
+            2 => Ok(Self::FullEstimate),
+            3 => Ok(Self::DoubleEstimate),
+            4 => Ok(Self::Conservative),
+            5 => Ok(Self::MempoolMin),
+            6 => Ok(Self::PayTxFee),
+            7 => Ok(Self::Fallback),
+            8 => Ok(Self::Required),
+            _ => Err(BlockTalkError::chain_error(
+                ChainErrorKind::DeserializationFailed,
+                format!("Unknown fee reason: {}", reason),
+            )),
+        }
+    }
+}
+
+/// Statistics of a fee rate bucket used by the estimator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EstimatorBucket {
+    pub start: f64,
+    pub end: f64,
+    pub within_target: f64,
+    pub total_confirmed: f64,
+    pub in_mempool: f64,
+    pub left_mempool: f64,
+}
+
+impl EstimatorBucket {
+    fn decode(reader: estimator_bucket::Reader) -> Self {
+        Self {
+            start: reader.get_start(),
+            end: reader.get_end(),
+            within_target: reader.get_within_target(),
+            total_confirmed: reader.get_total_confirmed(),
+            in_mempool: reader.get_in_mempool(),
+            left_mempool: reader.get_left_mempool(),
+        }
+    }
+}
+
+/// Buckets the estimate passed and failed in, as reported by `estimatesmartfee`
+/// with verbose diagnostics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EstimationDiagnostics {
+    pub pass: EstimatorBucket,
+    pub fail: EstimatorBucket,
+    pub decay: f64,
+    pub scale: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeEstimate {
+    /// Estimated fee rate, `None` if the node has not gathered enough data
+    pub fee_rate: Option<FeeRate>,
+    pub mode: EstimateMode,
+    /// Confirmation target that was asked for
+    pub desired_target: i32,
+    /// Confirmation target the estimate is actually for
+    pub returned_target: i32,
+    pub reason: FeeReason,
+    /// Bucket statistics, if requested
+    pub diagnostics: Option<EstimationDiagnostics>,
+}
+
+#[async_trait::async_trait]
+pub trait FeeEstimatorInterface: Send + Sync {
+    /// Estimate the fee rate needed to confirm within `target` blocks
+    async fn estimate_smart_fee(
+        &self,
+        target: u32,
+        mode: EstimateMode,
+        with_diagnostics: bool,
+    ) -> Result<FeeEstimate, BlockTalkError>;
+
+    /// Highest confirmation target the estimator supports
+    async fn estimate_max_blocks(&self) -> Result<u32, BlockTalkError>;
+
+    /// Minimum fee rate to enter the mempool right now
+    async fn mempool_min_fee(&self) -> Result<FeeRate, BlockTalkError>;
+
+    /// Minimum fee rate for relaying transactions (`-minrelaytxfee`)
+    async fn relay_min_fee(&self) -> Result<FeeRate, BlockTalkError>;
+
+    /// Minimum fee rate increase for replacements (`-incrementalrelayfee`)
+    async fn relay_incremental_fee(&self) -> Result<FeeRate, BlockTalkError>;
+
+    /// Fee rate used to determine dust outputs (`-dustrelayfee`)
+    async fn relay_dust_fee(&self) -> Result<FeeRate, BlockTalkError>;
+}
+
+pub struct FeeEstimator {
+    connection: Arc<Connection>,
+}
+
+/// Call a context-only `Chain` method returning a serialized `CFeeRate`.
+macro_rules! fee_rate_call {
+    ($self:ident, $request:ident, $what:literal) => {{
+        log::debug!("Fetching {}", $what);
+        let data = $self
+            .connection
+            .run(|clients| async move {
+                let req = with_thread!(clients.chain.$request(), clients.thread);
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get {}: {}", $what, e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result()?.to_vec())
+            })
+            .await?;
+        decode_fee_rate(&data)
+    }};
+}
+
+#[async_trait::async_trait]
+impl FeeEstimatorInterface for FeeEstimator {
+    async fn estimate_smart_fee(
+        &self,
+        target: u32,
+        mode: EstimateMode,
+        with_diagnostics: bool,
+    ) -> Result<FeeEstimate, BlockTalkError> {
+        log::debug!("Estimating fee for target {} ({:?})", target, mode);
+        let num_blocks = i32::try_from(target).map_err(|_| {
+            BlockTalkError::chain_error(
+                ChainErrorKind::Other("Invalid confirmation target".to_string()),
+                format!("Confirmation target {} is out of range", target),
+            )
+        })?;
+
+        let (data, desired_target, returned_target, reason, diagnostics) = self
+            .connection
+            .run(move |clients| async move {
+                let mut req =
+                    with_thread!(clients.chain.estimate_smart_fee_request(), clients.thread);
+
+                let mut params = req.get();
+                params.set_num_blocks(num_blocks);
+                params.set_conservative(mode == EstimateMode::Conservative);
+                params.set_want_calc(true);
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to estimate smart fee: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let result = response.get()?;
+                let calc: fee_calculation::Reader = result.get_calc()?;
+                let diagnostics = if with_diagnostics {
+                    let est = calc.get_est()?;
+                    Some(EstimationDiagnostics {
+                        pass: EstimatorBucket::decode(est.get_pass()?),
+                        fail: EstimatorBucket::decode(est.get_fail()?),
+                        decay: est.get_decay(),
+                        scale: est.get_scale(),
+                    })
+                } else {
+                    None
+                };
+
+                Ok((
+                    result.get_result()?.to_vec(),
+                    calc.get_desired_target(),
+                    calc.get_returned_target(),
+                    calc.get_reason(),
+                    diagnostics,
+                ))
+            })
+            .await?;
+
+        let fee_rate = decode_fee_rate(&data)?;
+        Ok(FeeEstimate {
+            fee_rate: (fee_rate != FeeRate::ZERO).then_some(fee_rate),
+            mode,
+            desired_target,
+            returned_target,
+            reason: FeeReason::try_from(reason)?,
+            diagnostics,
+        })
+    }
+
+    async fn estimate_max_blocks(&self) -> Result<u32, BlockTalkError> {
+        log::debug!("Fetching maximum fee estimation target");
+        self.connection
+            .run(|clients| async move {
+                let req = with_thread!(clients.chain.estimate_max_blocks_request(), clients.thread);
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get maximum estimation target: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }
+
+    async fn mempool_min_fee(&self) -> Result<FeeRate, BlockTalkError> {
+        fee_rate_call!(self, mempool_min_fee_request, "mempool minimum fee")
+    }
+
+    async fn relay_min_fee(&self) -> Result<FeeRate, BlockTalkError> {
+        fee_rate_call!(self, relay_min_fee_request, "relay minimum fee")
+    }
+
+    async fn relay_incremental_fee(&self) -> Result<FeeRate, BlockTalkError> {
+        fee_rate_call!(self, relay_incremental_fee_request, "incremental relay fee")
+    }
+
+    async fn relay_dust_fee(&self) -> Result<FeeRate, BlockTalkError> {
+        fee_rate_call!(self, relay_dust_fee_request, "dust relay fee")
+    }
+}
+
+impl FeeEstimator {
+    pub fn new(connection: Arc<Connection>) -> Self {
+        Self { connection }
+    }
+}
+
+/// Decode a serialized `CFeeRate` (satoshis per 1000 virtual bytes).
+///
+/// `FeeRate` counts per 1000 weight units, so the rate is rounded up to
+/// never fall below the node's value.
+pub(crate) fn decode_fee_rate(data: &[u8]) -> Result<FeeRate, BlockTalkError> {
+    let sat_per_kvb = <[u8; 8]>::try_from(data)
+        .map(i64::from_le_bytes)
+        .map_err(|_| {
+            log::error!("Invalid fee rate length: expected 8, got {}", data.len());
+            BlockTalkError::chain_error(
+                ChainErrorKind::DeserializationFailed,
+                format!("Invalid fee rate length: expected 8, got {}", data.len()),
+            )
+        })?;
+    let sat_per_kvb = u64::try_from(sat_per_kvb).map_err(|_| {
+        BlockTalkError::chain_error(
+            ChainErrorKind::DeserializationFailed,
+            format!("Negative fee rate: {}", sat_per_kvb),
+        )
+    })?;
+    Ok(FeeRate::from_sat_per_kwu(sat_per_kvb.div_ceil(4)))
+}
+
+/// Serialize a `FeeRate` as a `CFeeRate`.
+pub(crate) fn encode_fee_rate(fee_rate: FeeRate) -> Vec<u8> {
+    let sat_per_kvb = fee_rate.to_sat_per_kwu().saturating_mul(4);
+    i64::try_from(sat_per_kvb)
+        .unwrap_or(i64::MAX)
+        .to_le_bytes()
+        .to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_rate_roundtrip() {
+        let data = 1000i64.to_le_bytes();
+        let fee_rate = decode_fee_rate(&data).unwrap();
+        assert_eq!(fee_rate, FeeRate::from_sat_per_vb_unchecked(1));
+        assert_eq!(encode_fee_rate(fee_rate), data.to_vec());
+    }
+
+    #[test]
+    fn test_fee_rate_rounds_up() {
+        let fee_rate = decode_fee_rate(&1001i64.to_le_bytes()).unwrap();
+        assert_eq!(fee_rate.to_sat_per_kwu(), 251);
+    }
+
+    #[test]
+    fn test_invalid_fee_rate() {
+        assert!(decode_fee_rate(&(-1i64).to_le_bytes()).is_err());
+        assert!(decode_fee_rate(&[0u8; 4]).is_err());
+    }
+
+    #[test]
+    fn test_fee_reason_codes() {
+        // FeeReason in Core's policy/fees.h
+        let reasons = [
+            FeeReason::None,
+            FeeReason::HalfEstimate,
+            FeeReason::FullEstimate,
+            FeeReason::DoubleEstimate,
+            FeeReason::Conservative,
+            FeeReason::MempoolMin,
+            FeeReason::PayTxFee,
+            FeeReason::Fallback,
+            FeeReason::Required,
+        ];
+        for (code, reason) in reasons.into_iter().enumerate() {
+            assert_eq!(FeeReason::try_from(code as i32).unwrap(), reason);
+        }
+        assert!(FeeReason::try_from(9).is_err());
+        assert!(FeeReason::try_from(-1).is_err());
+    }
+}
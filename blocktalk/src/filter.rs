@@ -0,0 +1,247 @@
+use bitcoin::{BlockHash, ScriptBuf};
+use futures::{StreamExt, TryStreamExt};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::blocks::{check_below_tip, BlockStream};
+use crate::connection::with_thread;
+use crate::error::ChainErrorKind;
+use crate::{BlockQuery, BlockTalkError, ChainInterface, Connection};
+
+/// Number of filter checks kept in flight by `scan_range`
+const SCAN_PIPELINE_DEPTH: usize = 16;
+
+/// BIP157 block filter types, mirroring Core's `BlockFilterType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockFilterType {
+    /// BIP158 basic filter over output scripts and spent output scripts
+    Basic,
+}
+
+impl BlockFilterType {
+    fn code(self) -> u8 {
+        match self {
+            BlockFilterType::Basic => 0,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait BlockFilterInterface: Send + Sync {
+    /// Check whether the node maintains an index of `filter_type` filters
+    /// (`-blockfilterindex`)
+    async fn has_block_filter_index(
+        &self,
+        filter_type: BlockFilterType,
+    ) -> Result<bool, BlockTalkError>;
+
+    /// Check whether the block's filter matches any of `scripts`
+    /// Returns `None` if the node has no filter for the block yet. Matches
+    /// may be false positives
+    async fn block_filter_matches_any(
+        &self,
+        filter_type: BlockFilterType,
+        block_hash: &BlockHash,
+        scripts: &[ScriptBuf],
+    ) -> Result<Option<bool>, BlockTalkError>;
+
+    /// Stream the blocks in `heights` whose basic filter matches any of
+    /// `scripts`, anchored to the current tip like `ChainInterface::blocks`.
+    /// Only matching blocks, and blocks the node has no filter for yet, are
+    /// downloaded
+    async fn scan_range(
+        &self,
+        scripts: &[ScriptBuf],
+        heights: RangeInclusive<u32>,
+    ) -> Result<BlockStream, BlockTalkError>;
+}
+
+pub struct BlockFilters {
+    connection: Arc<Connection>,
+    chain: Arc<dyn ChainInterface>,
+}
+
+#[async_trait::async_trait]
+impl BlockFilterInterface for BlockFilters {
+    async fn has_block_filter_index(
+        &self,
+        filter_type: BlockFilterType,
+    ) -> Result<bool, BlockTalkError> {
+        log::debug!("Checking for {:?} block filter index", filter_type);
+        self.connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(
+                    clients.chain.has_block_filter_index_request(),
+                    clients.thread
+                );
+
+                req.get().set_filter_type(filter_type.code());
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to check block filter index: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }
+
+    async fn block_filter_matches_any(
+        &self,
+        filter_type: BlockFilterType,
+        block_hash: &BlockHash,
+        scripts: &[ScriptBuf],
+    ) -> Result<Option<bool>, BlockTalkError> {
+        log::debug!(
+            "Matching {} scripts against {:?} filter of block {}",
+            scripts.len(),
+            filter_type,
+            block_hash
+        );
+        let scripts = scripts.iter().map(|script| script.to_bytes()).collect();
+        filter_matches_any(
+            &self.connection,
+            filter_type,
+            *block_hash,
+            Arc::new(scripts),
+        )
+        .await
+    }
+
+    async fn scan_range(
+        &self,
+        scripts: &[ScriptBuf],
+        heights: RangeInclusive<u32>,
+    ) -> Result<BlockStream, BlockTalkError> {
+        if !self.has_block_filter_index(BlockFilterType::Basic).await? {
+            return Err(BlockTalkError::chain_error(
+                ChainErrorKind::Other("Block filter index not available".to_string()),
+                "The node is not running with -blockfilterindex=basic".to_string(),
+            ));
+        }
+
+        let (tip_height, tip) = self.chain.get_tip().await?;
+        log::debug!(
+            "Scanning blocks {:?} below tip {} for {} scripts",
+            heights,
+            tip,
+            scripts.len()
+        );
+        check_below_tip(&heights, tip_height)?;
+
+        let chain = self.chain.clone();
+        let connection = self.connection.clone();
+        let scripts: Arc<Vec<Vec<u8>>> =
+            Arc::new(scripts.iter().map(|script| script.to_bytes()).collect());
+        let query = BlockQuery {
+            hash: true,
+            ..Default::default()
+        };
+        let matches = futures::stream::iter(heights)
+            .map(move |height| {
+                let chain = chain.clone();
+                let connection = connection.clone();
+                let scripts = scripts.clone();
+                let query = query.clone();
+                async move {
+                    let hash = chain
+                        .query_ancestor(&tip, height as i32, &query)
+                        .await?
+                        .and_then(|meta| meta.hash)
+                        .ok_or_else(|| {
+                            BlockTalkError::chain_error(
+                                ChainErrorKind::BlockNotFound,
+                                format!("No block at height {} below {}", height, tip),
+                            )
+                        })?;
+                    let matches =
+                        filter_matches_any(&connection, BlockFilterType::Basic, hash, scripts)
+                            .await?;
+                    if matches.is_none() {
+                        log::debug!("No filter for block {}, fetching it", hash);
+                    }
+                    Ok::<_, BlockTalkError>(select_height(height, matches))
+                }
+            })
+            .buffered(SCAN_PIPELINE_DEPTH)
+            .try_filter_map(|height| async move { Ok(height) });
+
+        Ok(BlockStream::from_heights(
+            self.connection.clone(),
+            tip,
+            matches,
+        ))
+    }
+}
+
+impl BlockFilters {
+    pub fn new(connection: Arc<Connection>, chain: Arc<dyn ChainInterface>) -> Self {
+        Self { connection, chain }
+    }
+}
+
+/// Height to fetch for a block's filter match result. Blocks the node has no
+/// filter for yet are fetched too, since they may match
+fn select_height(height: u32, matches: Option<bool>) -> Option<u32> {
+    matches.unwrap_or(true).then_some(height)
+}
+
+/// Match `scripts` against the block's filter through `blockFilterMatchesAny`
+async fn filter_matches_any(
+    connection: &Connection,
+    filter_type: BlockFilterType,
+    block_hash: BlockHash,
+    scripts: Arc<Vec<Vec<u8>>>,
+) -> Result<Option<bool>, BlockTalkError> {
+    connection
+        .run(move |clients| async move {
+            let mut req = with_thread!(
+                clients.chain.block_filter_matches_any_request(),
+                clients.thread
+            );
+
+            let mut params = req.get();
+            params.set_filter_type(filter_type.code());
+            params.set_block_hash(block_hash.as_ref());
+            let mut filter_set = params.init_filter_set(scripts.len() as u32);
+            for (i, script) in scripts.iter().enumerate() {
+                filter_set.set(i as u32, script.as_slice());
+            }
+
+            let response = req.send().promise.await.map_err(|e| {
+                log::error!("Failed to match block filter of {}: {}", block_hash, e);
+                BlockTalkError::Connection(e.to_string())
+            })?;
+
+            let result = response.get()?;
+            Ok(result.get_has_result().then(|| result.get_result()))
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_type_code() {
+        // BlockFilterType::BASIC in Core's blockfilter.h
+        assert_eq!(BlockFilterType::Basic.code(), 0);
+    }
+
+    #[test]
+    fn test_select_height() {
+        assert_eq!(select_height(100, Some(true)), Some(100));
+        assert_eq!(select_height(100, Some(false)), None);
+        // No filter yet, the block has to be checked in full
+        assert_eq!(select_height(100, None), Some(100));
+
+        let results = [Some(false), Some(true), None, Some(false)];
+        let selected: Vec<u32> = (10..)
+            .zip(results)
+            .filter_map(|(height, matches)| select_height(height, matches))
+            .collect();
+        assert_eq!(selected, vec![11, 12]);
+    }
+}
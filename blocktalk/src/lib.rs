@@ -1,44 +1,109 @@
+use bitcoin::{Transaction, Txid};
+use futures::StreamExt;
 use std::sync::Arc;
+use std::time::Duration;
 
+mod blocks;
 mod chain;
+mod coin;
 mod connection;
 mod error;
+mod fees;
+mod filter;
 mod generated;
+mod locator;
 mod mempool;
+mod mining;
+mod mirror;
 mod notification;
+mod query;
+mod rpc;
+mod settings;
+mod snapshot;
+mod status;
+mod stream;
 
 pub use bitcoin::BlockHash;
+pub use blocks::BlockStream;
 pub use chain::{Blockchain, ChainInterface};
-pub use connection::{Connection, ConnectionProvider, UnixConnectionProvider};
+pub use coin::{Coin, TxUndo};
+pub use connection::{
+    Connection, ConnectionProvider, ConnectionState, ReconnectPolicy, RpcClients,
+    UnixConnectionProvider,
+};
 pub use error::BlockTalkError;
+pub use fees::{
+    EstimateMode, EstimationDiagnostics, EstimatorBucket, FeeEstimate, FeeEstimator,
+    FeeEstimatorInterface, FeeReason,
+};
+pub use filter::{BlockFilterInterface, BlockFilterType, BlockFilters};
 pub use generated::*;
-pub use mempool::{Mempool, MempoolInterface, TransactionAncestry};
-pub use notification::ChainNotification;
+pub use locator::BlockLocator;
+pub use mempool::{
+    BroadcastError, LimitViolation, MaxFee, Mempool, MempoolInterface, PackageLimits, RbfState,
+    TransactionAncestry,
+};
+pub use mining::{BlockCreateOptions, BlockTemplate, Mining, MiningInterface};
+pub use mirror::{FeeHistogramBucket, MempoolMirror, MirrorEntry, ProjectedBlock};
 pub use notification::NotificationHandler;
+pub use notification::{
+    BlockInfo, ChainNotification, ChainstateRole, DispatchMode, DispatchOptions,
+    HandlerErrorCallback, HandlerFailure, HandlerId, HandlerMetrics, MempoolRemovalReason,
+};
+pub use query::{BlockMeta, BlockQuery};
+pub use rpc::{
+    NodeRpc, RpcArg, RpcCommand, RpcError, RpcHandler, RpcInterface, RpcRegistration, RpcRequest,
+    RpcRequestMode,
+};
+pub use settings::{NodeSettings, SettingsAction, SettingsInterface, SettingsUpdate};
+pub use snapshot::MempoolSnapshot;
+pub use status::{Node, NodeStatus, NodeStatusInterface};
+pub use stream::{LagPolicy, NotificationStream, StreamItem, SubscribeOptions};
 
+/// Handle to a `bitcoin-node` process.
+///
+/// All IPC traffic runs on a dedicated RPC thread owned by the [`Connection`],
+/// so the handle is `Send + Sync + Clone` and can be shared across tasks and
+/// runtimes without a `tokio::task::LocalSet`.
 #[derive(Clone)]
 pub struct BlockTalk {
     connection: Arc<Connection>,
     chain: Arc<dyn ChainInterface>,
     mempool: Arc<dyn MempoolInterface>,
+    mining: Arc<dyn MiningInterface>,
+    fees: Arc<dyn FeeEstimatorInterface>,
+    filters: Arc<dyn BlockFilterInterface>,
+    node: Arc<dyn NodeStatusInterface>,
+    settings: Arc<dyn SettingsInterface>,
+    rpc: Arc<dyn RpcInterface>,
 }
 
 impl BlockTalk {
     pub async fn init(socket_path: &str) -> Result<Self, BlockTalkError> {
         log::info!("Initializing BlockTalk with socket path: {}", socket_path);
         let connection = Connection::connect_default(socket_path).await?;
-        let chain = Arc::new(Blockchain::new(connection.clone()));
-        let mempool = Arc::new(Mempool::new(
-            connection.chain_client().clone(),
-            connection.thread().clone(),
-        ));
         log::info!("BlockTalk initialized successfully");
 
-        Ok(Self {
-            connection,
-            chain,
-            mempool,
-        })
+        Ok(Self::with_defaults(connection))
+    }
+
+    /// Like [`BlockTalk::init`], but reconnects with `policy` when the node
+    /// restarts. Connection state changes are available through
+    /// [`Connection::state_changes`].
+    pub async fn init_supervised(
+        socket_path: &str,
+        policy: ReconnectPolicy,
+    ) -> Result<Self, BlockTalkError> {
+        log::info!(
+            "Initializing supervised BlockTalk with socket path: {}",
+            socket_path
+        );
+        let connection =
+            Connection::connect_supervised(socket_path, Box::new(UnixConnectionProvider), policy)
+                .await?;
+        log::info!("BlockTalk initialized successfully");
+
+        Ok(Self::with_defaults(connection))
     }
 
     pub async fn init_with(
@@ -54,11 +119,38 @@ impl BlockTalk {
         let connection = Connection::connect(socket_path, chain_provider).await?;
         log::info!("BlockTalk initialized successfully");
 
-        Ok(Self {
+        Ok(Self::with_interfaces(
             connection,
-            chain: chain_interface,
-            mempool: mempool_interface,
-        })
+            chain_interface,
+            mempool_interface,
+        ))
+    }
+
+    /// Default implementations of every interface on `connection`.
+    fn with_defaults(connection: Arc<Connection>) -> Self {
+        let chain = Arc::new(Blockchain::new(connection.clone()));
+        let mempool = Arc::new(Mempool::new(connection.clone()));
+        Self::with_interfaces(connection, chain, mempool)
+    }
+
+    /// Default implementations of every interface except `chain` and
+    /// `mempool` on `connection`.
+    fn with_interfaces(
+        connection: Arc<Connection>,
+        chain: Arc<dyn ChainInterface>,
+        mempool: Arc<dyn MempoolInterface>,
+    ) -> Self {
+        Self {
+            mining: Arc::new(Mining::new(connection.clone())),
+            fees: Arc::new(FeeEstimator::new(connection.clone())),
+            filters: Arc::new(BlockFilters::new(connection.clone(), chain.clone())),
+            node: Arc::new(Node::new(connection.clone())),
+            settings: Arc::new(NodeSettings::new(connection.clone())),
+            rpc: Arc::new(NodeRpc::new(connection.clone())),
+            connection,
+            chain,
+            mempool,
+        }
     }
 
     pub fn chain(&self) -> &Arc<dyn ChainInterface> {
@@ -69,10 +161,137 @@ impl BlockTalk {
         &self.mempool
     }
 
-    pub async fn disconnect(self) -> Result<(), BlockTalkError> {
-        match Arc::try_unwrap(self.connection) {
-            Ok(conn) => conn.disconnect().await,
-            Err(_) => Ok(()),
+    pub fn mining(&self) -> &Arc<dyn MiningInterface> {
+        &self.mining
+    }
+
+    /// Replace the default `Mining`.
+    pub fn with_mining(mut self, mining: Arc<dyn MiningInterface>) -> Self {
+        self.mining = mining;
+        self
+    }
+
+    pub fn fees(&self) -> &Arc<dyn FeeEstimatorInterface> {
+        &self.fees
+    }
+
+    /// Replace the default `FeeEstimator`.
+    pub fn with_fee_estimator(mut self, fees: Arc<dyn FeeEstimatorInterface>) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Broadcast `tx` and wait until the node reports it entering the
+    /// mempool, or fail with `BroadcastError::Timeout` after `timeout`.
+    pub async fn broadcast_and_wait(
+        &self,
+        tx: &Transaction,
+        max_fee: MaxFee,
+        timeout: Duration,
+    ) -> Result<Txid, BroadcastError> {
+        let txid = tx.compute_txid();
+        // No notification is sent for a transaction that is already there
+        if self.mempool.is_in_mempool(&txid).await? {
+            return Ok(txid);
         }
+
+        let mut stream = self.chain.subscribe(SubscribeOptions::default()).await?;
+        self.mempool
+            .broadcast_transaction(tx, max_fee, true)
+            .await?;
+
+        let accepted = async {
+            while let Some(item) = stream.next().await {
+                match item {
+                    StreamItem::Notification(ChainNotification::TransactionAddedToMempool(
+                        added,
+                    )) if added.compute_txid() == txid => {
+                        return Ok(());
+                    }
+                    // The notification may have been dropped, ask directly
+                    StreamItem::Lagged(_) => {
+                        if self.mempool.is_in_mempool(&txid).await? {
+                            return Ok(());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Err(BroadcastError::Node(BlockTalkError::Connection(
+                "Notification stream closed before the transaction was accepted".to_string(),
+            )))
+        };
+
+        match tokio::time::timeout(timeout, accepted).await {
+            Ok(result) => result.map(|()| txid),
+            Err(_) => {
+                log::debug!(
+                    "Transaction {} not seen in mempool after {:?}",
+                    txid,
+                    timeout
+                );
+                Err(BroadcastError::Timeout(timeout))
+            }
+        }
+    }
+
+    pub fn filters(&self) -> &Arc<dyn BlockFilterInterface> {
+        &self.filters
+    }
+
+    /// Replace the default `BlockFilters`.
+    pub fn with_block_filters(mut self, filters: Arc<dyn BlockFilterInterface>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    pub fn node(&self) -> &Arc<dyn NodeStatusInterface> {
+        &self.node
+    }
+
+    /// Replace the default `Node` status interface.
+    pub fn with_node_status(mut self, node: Arc<dyn NodeStatusInterface>) -> Self {
+        self.node = node;
+        self
+    }
+
+    pub fn settings(&self) -> &Arc<dyn SettingsInterface> {
+        &self.settings
+    }
+
+    /// Replace the default `NodeSettings`.
+    pub fn with_settings(mut self, settings: Arc<dyn SettingsInterface>) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn rpc(&self) -> &Arc<dyn RpcInterface> {
+        &self.rpc
+    }
+
+    /// Replace the default `NodeRpc`.
+    pub fn with_rpc(mut self, rpc: Arc<dyn RpcInterface>) -> Self {
+        self.rpc = rpc;
+        self
+    }
+
+    pub fn connection(&self) -> &Arc<Connection> {
+        &self.connection
+    }
+
+    pub async fn disconnect(self) -> Result<(), BlockTalkError> {
+        self.connection.disconnect().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send_sync_clone<T: Send + Sync + Clone + 'static>() {}
+
+    #[test]
+    fn test_blocktalk_is_send_sync_clone() {
+        assert_send_sync_clone::<BlockTalk>();
     }
 }
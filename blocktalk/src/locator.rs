@@ -0,0 +1,81 @@
+use bitcoin::consensus::{encode, Decodable, Encodable};
+use bitcoin::io::{BufRead, Write};
+use bitcoin::BlockHash;
+
+use crate::error::ChainErrorKind;
+use crate::BlockTalkError;
+
+/// Version written by Core's `CBlockLocator` serialization and ignored when
+/// reading.
+const DUMMY_VERSION: i32 = 70016;
+
+/// Hashes describing a position in the chain, densest near the tip, as used
+/// by Core's wallet to resume after restarts and reorgs.
+///
+/// Encodes like Core's `CBlockLocator`, so a locator can be persisted with
+/// `bitcoin::consensus::serialize` and handed back to the node later.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockLocator {
+    pub hashes: Vec<BlockHash>,
+}
+
+impl BlockLocator {
+    pub fn new(hashes: Vec<BlockHash>) -> Self {
+        Self { hashes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Decode a serialized locator as returned by the node
+    pub(crate) fn decode(data: &[u8]) -> Result<Self, BlockTalkError> {
+        bitcoin::consensus::deserialize(data).map_err(|e| {
+            log::error!("Failed to decode block locator: {}", e);
+            BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+        })
+    }
+}
+
+impl Encodable for BlockLocator {
+    fn consensus_encode<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+    ) -> Result<usize, bitcoin::io::Error> {
+        let mut len = DUMMY_VERSION.consensus_encode(writer)?;
+        len += self.hashes.consensus_encode(writer)?;
+        Ok(len)
+    }
+}
+
+impl Decodable for BlockLocator {
+    fn consensus_decode<R: BufRead + ?Sized>(reader: &mut R) -> Result<Self, encode::Error> {
+        let _version = i32::consensus_decode(reader)?;
+        let hashes = Vec::<BlockHash>::consensus_decode(reader)?;
+        Ok(Self { hashes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    #[test]
+    fn test_locator_roundtrip() {
+        let locator = BlockLocator::new(vec![
+            BlockHash::from_byte_array([1; 32]),
+            BlockHash::from_byte_array([2; 32]),
+        ]);
+        let data = bitcoin::consensus::serialize(&locator);
+
+        assert_eq!(data.len(), 4 + 1 + 2 * 32);
+        assert_eq!(&data[..5], &[0x80, 0x11, 0x01, 0x00, 0x02]);
+        assert_eq!(BlockLocator::decode(&data).unwrap(), locator);
+    }
+
+    #[test]
+    fn test_decode_invalid_locator() {
+        assert!(BlockLocator::decode(&[0x80, 0x11, 0x01, 0x00, 0x01]).is_err());
+    }
+}
@@ -1,11 +1,17 @@
-use bitcoin::hashes::Hash;
-use bitcoin::{Transaction, Txid};
+use bitcoin::{Amount, FeeRate, OutPoint, Transaction, Txid};
+use futures::TryStreamExt;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::{
-    chain_capnp::chain::Client as ChainClient, proxy_capnp::thread::Client as ThreadClient,
-    BlockTalkError,
-};
+use crate::coin::find_coins;
+use crate::connection::with_thread;
+use crate::error::ChainErrorKind;
+use crate::fees::encode_fee_rate;
+use crate::snapshot::MempoolSnapshot;
+use crate::{BlockTalkError, Connection};
 
 #[derive(Debug)]
 pub struct TransactionAncestry {
@@ -19,56 +25,295 @@ pub struct TransactionAncestry {
     pub ancestor_fees: i64,
 }
 
-#[async_trait::async_trait(?Send)]
-pub trait MempoolInterface {
+/// BIP125 replaceability of a transaction, mirroring Core's `RBFTransactionState`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RbfState {
+    /// The transaction or one of its unconfirmed ancestors signals replaceability
+    OptedIn,
+    /// Neither the transaction nor its unconfirmed ancestors signal replaceability
+    NotOptedIn,
+    /// An unconfirmed ancestor is not in the mempool, so the state can't be determined
+    Unknown,
+}
+
+impl TryFrom<i32> for RbfState {
+    type Error = BlockTalkError;
+
+    fn try_from(state: i32) -> Result<Self, Self::Error> {
+        match state {
+            0 => Ok(Self::Unknown),
+            1 => Ok(Self::OptedIn),
+            2 => Ok(Self::NotOptedIn),
+            _ => Err(BlockTalkError::chain_error(
+                ChainErrorKind::DeserializationFailed,
+                format!("Unknown RBF state: {}", state),
+            )),
+        }
+    }
+}
+
+/// Upper bound on the fee paid by a broadcast transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaxFee {
+    /// No limit
+    Unlimited,
+    /// Absolute fee
+    Absolute(Amount),
+    /// Fee rate, converted to an absolute fee using the transaction's vsize
+    Rate(FeeRate),
+}
+
+impl Default for MaxFee {
+    /// `sendrawtransaction`'s default `maxfeerate` of 0.10 BTC/kvB
+    fn default() -> Self {
+        MaxFee::Rate(FeeRate::from_sat_per_vb_unchecked(10_000))
+    }
+}
+
+impl MaxFee {
+    /// Absolute limit in satoshis as expected by the node, 0 meaning no limit
+    fn to_sat(self, tx: &Transaction) -> i64 {
+        let fee = match self {
+            MaxFee::Unlimited => return 0,
+            MaxFee::Absolute(fee) => Some(fee),
+            MaxFee::Rate(rate) => rate.fee_vb(tx.vsize() as u64),
+        };
+        fee.and_then(|fee| i64::try_from(fee.to_sat()).ok())
+            .unwrap_or(i64::MAX)
+    }
+}
+
+/// Why a transaction was not accepted by `broadcast_transaction`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BroadcastError {
+    /// Outputs of the transaction are already in the UTXO set
+    AlreadyInChain,
+    /// The transaction (or one with the same witness-stripped id) is
+    /// already in the mempool
+    AlreadyInMempool,
+    /// Fee below the mempool or relay minimum, or too low to replace a
+    /// conflicting transaction
+    InsufficientFee { reason: String },
+    /// Fee above the `MaxFee` given to `broadcast_transaction`
+    MaxFeeExceeded,
+    /// Inputs are missing or already spent
+    MissingInputs,
+    /// Rejected by mempool policy or consensus checks
+    Rejected { reason: String },
+    /// Not seen entering the mempool within the given time
+    Timeout(Duration),
+    /// The call itself failed
+    Node(BlockTalkError),
+}
+
+impl BroadcastError {
+    /// Classify the node's reject reason (`TxValidationState::ToString`).
+    pub(crate) fn from_reject_reason(reason: String) -> Self {
+        let code = reason.split(',').next().unwrap_or_default();
+        match code {
+            "txn-already-in-mempool"
+            | "txn-already-known"
+            | "txn-same-nonwitness-data-in-mempool" => BroadcastError::AlreadyInMempool,
+            "bad-txns-inputs-missingorspent" | "missing-inputs" => BroadcastError::MissingInputs,
+            "min relay fee not met" | "mempool min fee not met" | "insufficient fee" => {
+                BroadcastError::InsufficientFee { reason }
+            }
+            "max-fee-exceeded" => BroadcastError::MaxFeeExceeded,
+            _ => BroadcastError::Rejected { reason },
+        }
+    }
+}
+
+impl fmt::Display for BroadcastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BroadcastError::AlreadyInChain => write!(f, "Transaction already in chain"),
+            BroadcastError::AlreadyInMempool => write!(f, "Transaction already in mempool"),
+            BroadcastError::InsufficientFee { reason } => {
+                write!(f, "Insufficient fee: {}", reason)
+            }
+            BroadcastError::MaxFeeExceeded => write!(f, "Fee exceeds maximum"),
+            BroadcastError::MissingInputs => write!(f, "Inputs missing or spent"),
+            BroadcastError::Rejected { reason } => write!(f, "Transaction rejected: {}", reason),
+            BroadcastError::Timeout(timeout) => {
+                write!(f, "Transaction not accepted within {:?}", timeout)
+            }
+            BroadcastError::Node(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for BroadcastError {}
+
+impl From<BlockTalkError> for BroadcastError {
+    fn from(error: BlockTalkError) -> Self {
+        BroadcastError::Node(error)
+    }
+}
+
+/// Ancestor and descendant count limits of the node's mempool policy
+/// (`-limitancestorcount` / `-limitdescendantcount`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackageLimits {
+    pub ancestors: u64,
+    pub descendants: u64,
+}
+
+/// Mempool chain limit a transaction would exceed, parsed from the node's
+/// error message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitViolation {
+    /// Too many unconfirmed ancestors
+    AncestorCount { limit: u64 },
+    /// Unconfirmed ancestors too large, in virtual bytes
+    AncestorSize { limit: u64 },
+    /// Too many descendants of an unconfirmed ancestor
+    DescendantCount { limit: u64 },
+    /// Descendants of an unconfirmed ancestor too large, in virtual bytes
+    DescendantSize { limit: u64 },
+    /// Too many direct unconfirmed parents
+    ParentCount { limit: u64 },
+    /// A limit error this version does not recognize
+    Other { reason: String },
+    /// The call itself failed
+    Node(BlockTalkError),
+}
+
+impl LimitViolation {
+    /// Parse one of the `CTxMemPool` limit errors, e.g.
+    /// `"too many unconfirmed ancestors [limit: 25]"`.
+    pub(crate) fn parse(message: &str) -> Option<Self> {
+        let (_, rest) = message.rsplit_once("[limit: ")?;
+        let limit = rest.strip_suffix(']')?.parse().ok()?;
+        let violation = if message.contains("unconfirmed parents") {
+            Self::ParentCount { limit }
+        } else if message.contains("ancestor size") {
+            Self::AncestorSize { limit }
+        } else if message.contains("ancestor") {
+            Self::AncestorCount { limit }
+        } else if message.contains("descendant size") {
+            Self::DescendantSize { limit }
+        } else if message.contains("descendant") {
+            Self::DescendantCount { limit }
+        } else {
+            return None;
+        };
+        Some(violation)
+    }
+}
+
+impl fmt::Display for LimitViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitViolation::AncestorCount { limit } => {
+                write!(f, "Too many unconfirmed ancestors (limit {})", limit)
+            }
+            LimitViolation::AncestorSize { limit } => {
+                write!(f, "Ancestor size exceeds {} vB", limit)
+            }
+            LimitViolation::DescendantCount { limit } => {
+                write!(f, "Too many descendants (limit {})", limit)
+            }
+            LimitViolation::DescendantSize { limit } => {
+                write!(f, "Descendant size exceeds {} vB", limit)
+            }
+            LimitViolation::ParentCount { limit } => {
+                write!(f, "Too many unconfirmed parents (limit {})", limit)
+            }
+            LimitViolation::Other { reason } => write!(f, "Chain limit exceeded: {}", reason),
+            LimitViolation::Node(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for LimitViolation {}
+
+impl From<BlockTalkError> for LimitViolation {
+    fn from(error: BlockTalkError) -> Self {
+        LimitViolation::Node(error)
+    }
+}
+
+#[async_trait::async_trait]
+pub trait MempoolInterface: Send + Sync {
     /// Check if a transaction is in the mempool
     async fn is_in_mempool(&self, txid: &Txid) -> Result<bool, BlockTalkError>;
 
     /// Check if a transaction has descendants in the mempool
     async fn has_descendants_in_mempool(&self, txid: &Txid) -> Result<bool, BlockTalkError>;
 
-    /// Broadcast a transaction to the network
+    /// Submit a transaction to the mempool and, if `relay` is set, announce
+    /// it to peers. A transaction already in the mempool is accepted again
     async fn broadcast_transaction(
         &self,
         tx: &Transaction,
-        max_tx_fee: i64,
+        max_fee: MaxFee,
         relay: bool,
-    ) -> Result<(String, bool), BlockTalkError>;
+    ) -> Result<Txid, BroadcastError>;
 
     /// Get transaction ancestry information
     async fn get_transaction_ancestry(
         &self,
         txid: &Txid,
     ) -> Result<TransactionAncestry, BlockTalkError>;
+
+    /// Check whether a transaction signals BIP125 replaceability
+    async fn is_rbf_opt_in(&self, tx: &Transaction) -> Result<RbfState, BlockTalkError>;
+
+    /// Fee each outpoint needs on top of its own to bring its unconfirmed
+    /// ancestors up to `target_feerate`, for building CPFP children
+    async fn calculate_individual_bump_fees(
+        &self,
+        outpoints: &[OutPoint],
+        target_feerate: FeeRate,
+    ) -> Result<HashMap<OutPoint, Amount>, BlockTalkError>;
+
+    /// Fee needed to bring the combined unconfirmed ancestry of `outpoints` up
+    /// to `target_feerate`, accounting for shared ancestors. `None` if the
+    /// node could not compute it
+    async fn calculate_combined_bump_fee(
+        &self,
+        outpoints: &[OutPoint],
+        target_feerate: FeeRate,
+    ) -> Result<Option<Amount>, BlockTalkError>;
+
+    /// Ancestor and descendant count limits enforced by the node
+    async fn package_limits(&self) -> Result<PackageLimits, BlockTalkError>;
+
+    /// Check that adding `tx` would not exceed the mempool's chain limits
+    async fn check_chain_limits(&self, tx: &Transaction) -> Result<(), LimitViolation>;
+
+    /// All transactions currently in the mempool. Combined with a
+    /// subscription started beforehand, this bootstraps a complete mempool view
+    async fn snapshot(&self) -> Result<Vec<Transaction>, BlockTalkError>;
+
+    /// Like `snapshot`, but yields transactions as the node sends them
+    async fn snapshot_stream(&self) -> Result<MempoolSnapshot, BlockTalkError>;
 }
 
 pub struct Mempool {
-    chain_client: ChainClient,
-    thread: ThreadClient,
+    connection: Arc<Connection>,
 }
 
-#[async_trait::async_trait(?Send)]
+#[async_trait::async_trait]
 impl MempoolInterface for Mempool {
     async fn is_in_mempool(&self, txid: &Txid) -> Result<bool, BlockTalkError> {
         log::debug!("Checking if transaction {} is in mempool", txid);
-        let mut req = self.chain_client.is_in_mempool_request();
+        let txid = *txid;
+        self.connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(clients.chain.is_in_mempool_request(), clients.thread);
 
-        req.get()
-            .get_context()
-            .map_err(|e| {
-                log::error!("Failed to get mempool context: {}", e);
-                BlockTalkError::Connection(e.to_string())
-            })?
-            .set_thread(self.thread.clone());
+                req.get().set_txid(txid.as_ref());
 
-        req.get().set_txid(txid.as_ref());
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to check mempool status: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
 
-        let response = req.send().promise.await.map_err(|e| {
-            log::error!("Failed to check mempool status: {}", e);
-            BlockTalkError::Connection(e.to_string())
-        })?;
-
-        Ok(response.get()?.get_result())
+                Ok(response.get()?.get_result())
+            })
+            .await
     }
 
     async fn has_descendants_in_mempool(&self, txid: &Txid) -> Result<bool, BlockTalkError> {
@@ -76,61 +321,80 @@ impl MempoolInterface for Mempool {
             "Checking if transaction {} has descendants in mempool",
             txid
         );
-        let mut req = self.chain_client.has_descendants_in_mempool_request();
-
-        req.get()
-            .get_context()
-            .map_err(|e| {
-                log::error!("Failed to get mempool context: {}", e);
-                BlockTalkError::Connection(e.to_string())
-            })?
-            .set_thread(self.thread.clone());
+        let txid = *txid;
+        self.connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(
+                    clients.chain.has_descendants_in_mempool_request(),
+                    clients.thread
+                );
 
-        req.get().set_txid(txid.as_ref());
+                req.get().set_txid(txid.as_ref());
 
-        let response = req.send().promise.await.map_err(|e| {
-            log::error!("Failed to check descendants: {}", e);
-            BlockTalkError::Connection(e.to_string())
-        })?;
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to check descendants: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
 
-        Ok(response.get()?.get_result())
+                Ok(response.get()?.get_result())
+            })
+            .await
     }
 
     async fn broadcast_transaction(
         &self,
         tx: &Transaction,
-        max_tx_fee: i64,
+        max_fee: MaxFee,
         relay: bool,
-    ) -> Result<(String, bool), BlockTalkError> {
-        log::debug!("Broadcasting transaction {}", tx.compute_txid());
-        let mut req = self.chain_client.broadcast_transaction_request();
-
-        req.get()
-            .get_context()
-            .map_err(|e| {
-                log::error!("Failed to get broadcast context: {}", e);
-                BlockTalkError::Connection(e.to_string())
-            })?
-            .set_thread(self.thread.clone());
-
-        let mut params = req.get();
-        params.set_tx(bitcoin::consensus::serialize(tx).as_slice());
-        params.set_max_tx_fee(max_tx_fee);
-        params.set_relay(relay);
-
-        let response = req.send().promise.await.map_err(|e| {
-            log::error!("Failed to broadcast transaction: {}", e);
-            BlockTalkError::Connection(e.to_string())
-        })?;
-
-        let result = response.get()?;
-        Ok((
-            result
-                .get_error()?
-                .to_string()
-                .map_err(|e| BlockTalkError::Connection(e.to_string()))?,
-            result.get_result(),
-        ))
+    ) -> Result<Txid, BroadcastError> {
+        let txid = tx.compute_txid();
+        log::debug!("Broadcasting transaction {}", txid);
+        let tx_data = bitcoin::consensus::serialize(tx);
+        let max_tx_fee = max_fee.to_sat(tx);
+        let (error, accepted) = self
+            .connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(
+                    clients.chain.broadcast_transaction_request(),
+                    clients.thread
+                );
+
+                let mut params = req.get();
+                params.set_tx(tx_data.as_slice());
+                params.set_max_tx_fee(max_tx_fee);
+                params.set_relay(relay);
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to broadcast transaction: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let result = response.get()?;
+                Ok((
+                    result
+                        .get_error()?
+                        .to_string()
+                        .map_err(|e| BlockTalkError::Connection(e.to_string()))?,
+                    result.get_result(),
+                ))
+            })
+            .await?;
+
+        if accepted {
+            return Ok(txid);
+        }
+        log::debug!("Transaction {} rejected: {:?}", txid, error);
+        if !error.is_empty() {
+            return Err(BroadcastError::from_reject_reason(error));
+        }
+        // The node only reports a reason for mempool rejections. The
+        // remaining failures are outputs already in the UTXO set and the
+        // max fee check.
+        if self.has_unspent_outputs(tx).await? {
+            Err(BroadcastError::AlreadyInChain)
+        } else {
+            Err(BroadcastError::MaxFeeExceeded)
+        }
     }
 
     async fn get_transaction_ancestry(
@@ -138,38 +402,329 @@ impl MempoolInterface for Mempool {
         txid: &Txid,
     ) -> Result<TransactionAncestry, BlockTalkError> {
         log::debug!("Getting ancestry for transaction {}", txid);
-        let mut req = self.chain_client.get_transaction_ancestry_request();
+        let txid = *txid;
+        self.connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(
+                    clients.chain.get_transaction_ancestry_request(),
+                    clients.thread
+                );
+
+                req.get().set_txid(txid.as_ref());
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get transaction ancestry: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let result = response.get()?;
+                Ok(TransactionAncestry {
+                    ancestors: result.get_ancestors(),
+                    descendants: result.get_descendants(),
+                    ancestor_size: result.get_ancestorsize(),
+                    ancestor_fees: result.get_ancestorfees(),
+                })
+            })
+            .await
+    }
+
+    async fn is_rbf_opt_in(&self, tx: &Transaction) -> Result<RbfState, BlockTalkError> {
+        log::debug!("Checking RBF state of transaction {}", tx.compute_txid());
+        let tx_data = bitcoin::consensus::serialize(tx);
+        let state = self
+            .connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(clients.chain.is_r_b_f_opt_in_request(), clients.thread);
+
+                req.get().set_tx(tx_data.as_slice());
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to check RBF state: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result())
+            })
+            .await?;
+
+        RbfState::try_from(state)
+    }
+
+    async fn calculate_individual_bump_fees(
+        &self,
+        outpoints: &[OutPoint],
+        target_feerate: FeeRate,
+    ) -> Result<HashMap<OutPoint, Amount>, BlockTalkError> {
+        log::debug!(
+            "Calculating individual bump fees for {} outpoints at {}",
+            outpoints.len(),
+            target_feerate
+        );
+        let keys: Vec<Vec<u8>> = outpoints
+            .iter()
+            .map(bitcoin::consensus::serialize)
+            .collect();
+        let feerate = encode_fee_rate(target_feerate);
 
-        req.get()
-            .get_context()
-            .map_err(|e| {
-                log::error!("Failed to get ancestry context: {}", e);
-                BlockTalkError::Connection(e.to_string())
-            })?
-            .set_thread(self.thread.clone());
+        let fees = self
+            .connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(
+                    clients.chain.calculate_individual_bump_fees_request(),
+                    clients.thread
+                );
+
+                let mut params = req.get();
+                params.set_target_feerate(feerate.as_slice());
+                let mut list = params.init_outpoints(keys.len() as u32);
+                for (i, key) in keys.iter().enumerate() {
+                    list.set(i as u32, key.as_slice());
+                }
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to calculate bump fees: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let mut fees = Vec::new();
+                for entry in response.get()?.get_result()?.iter() {
+                    fees.push((entry.get_key()?.to_vec(), entry.get_value()));
+                }
+                Ok(fees)
+            })
+            .await?;
+
+        fees.into_iter()
+            .map(|(key, fee)| {
+                let outpoint: OutPoint = bitcoin::consensus::deserialize(&key).map_err(|e| {
+                    log::error!("Failed to decode outpoint: {}", e);
+                    BlockTalkError::chain_error(
+                        ChainErrorKind::DeserializationFailed,
+                        e.to_string(),
+                    )
+                })?;
+                Ok((outpoint, bump_fee_amount(fee)?))
+            })
+            .collect()
+    }
+
+    async fn calculate_combined_bump_fee(
+        &self,
+        outpoints: &[OutPoint],
+        target_feerate: FeeRate,
+    ) -> Result<Option<Amount>, BlockTalkError> {
+        log::debug!(
+            "Calculating combined bump fee for {} outpoints at {}",
+            outpoints.len(),
+            target_feerate
+        );
+        let keys: Vec<Vec<u8>> = outpoints
+            .iter()
+            .map(bitcoin::consensus::serialize)
+            .collect();
+        let feerate = encode_fee_rate(target_feerate);
+
+        let fee = self
+            .connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(
+                    clients.chain.calculate_combined_bump_fee_request(),
+                    clients.thread
+                );
+
+                let mut params = req.get();
+                params.set_target_feerate(feerate.as_slice());
+                let mut list = params.init_outpoints(keys.len() as u32);
+                for (i, key) in keys.iter().enumerate() {
+                    list.set(i as u32, key.as_slice());
+                }
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to calculate combined bump fee: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let result = response.get()?;
+                Ok(result.get_has_result().then(|| result.get_result()))
+            })
+            .await?;
+
+        fee.map(bump_fee_amount).transpose()
+    }
 
-        req.get().set_txid(txid.as_ref());
+    async fn package_limits(&self) -> Result<PackageLimits, BlockTalkError> {
+        log::debug!("Getting mempool package limits");
+        self.connection
+            .run(|clients| async move {
+                let req = with_thread!(clients.chain.get_package_limits_request(), clients.thread);
 
-        let response = req.send().promise.await.map_err(|e| {
-            log::error!("Failed to get transaction ancestry: {}", e);
-            BlockTalkError::Connection(e.to_string())
-        })?;
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get package limits: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
 
-        let result = response.get()?;
-        Ok(TransactionAncestry {
-            ancestors: result.get_ancestors(),
-            descendants: result.get_descendants(),
-            ancestor_size: result.get_ancestorsize(),
-            ancestor_fees: result.get_ancestorfees(),
-        })
+                let result = response.get()?;
+                Ok(PackageLimits {
+                    ancestors: result.get_ancestors(),
+                    descendants: result.get_descendants(),
+                })
+            })
+            .await
     }
+
+    async fn check_chain_limits(&self, tx: &Transaction) -> Result<(), LimitViolation> {
+        log::debug!(
+            "Checking chain limits for transaction {}",
+            tx.compute_txid()
+        );
+        let tx_data = bitcoin::consensus::serialize(tx);
+        let error = self
+            .connection
+            .run(move |clients| async move {
+                let mut req =
+                    with_thread!(clients.chain.check_chain_limits_request(), clients.thread);
+
+                req.get().set_tx(tx_data.as_slice());
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to check chain limits: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let result = response.get()?.get_result()?;
+                if !result.has_error() {
+                    return Ok(None);
+                }
+                let message = result
+                    .get_error()?
+                    .get_original()?
+                    .to_string()
+                    .map_err(|e| BlockTalkError::Connection(e.to_string()))?;
+                Ok(Some(message))
+            })
+            .await?;
+
+        match error {
+            None => Ok(()),
+            Some(message) => {
+                log::debug!("Transaction exceeds chain limits: {}", message);
+                Err(LimitViolation::parse(&message)
+                    .unwrap_or(LimitViolation::Other { reason: message }))
+            }
+        }
+    }
+
+    async fn snapshot(&self) -> Result<Vec<Transaction>, BlockTalkError> {
+        let txs: Vec<Transaction> = self.snapshot_stream().await?.try_collect().await?;
+        log::debug!("Received {} mempool transactions", txs.len());
+        Ok(txs)
+    }
+
+    async fn snapshot_stream(&self) -> Result<MempoolSnapshot, BlockTalkError> {
+        log::debug!("Requesting mempool transactions");
+        Ok(MempoolSnapshot::request(&self.connection))
+    }
+}
+
+fn bump_fee_amount(fee: i64) -> Result<Amount, BlockTalkError> {
+    u64::try_from(fee).map(Amount::from_sat).map_err(|_| {
+        log::error!("Node returned negative bump fee: {}", fee);
+        BlockTalkError::chain_error(
+            ChainErrorKind::InvalidBlockData,
+            format!("Negative bump fee: {}", fee),
+        )
+    })
 }
 
 impl Mempool {
-    pub fn new(chain_client: ChainClient, thread: ThreadClient) -> Self {
-        Self {
-            chain_client,
-            thread,
+    /// Whether any output of `tx` is in the node's UTXO set
+    async fn has_unspent_outputs(&self, tx: &Transaction) -> Result<bool, BlockTalkError> {
+        let txid = tx.compute_txid();
+        let outpoints: Vec<OutPoint> = (0..tx.output.len() as u32)
+            .map(|vout| OutPoint::new(txid, vout))
+            .collect();
+        let coins = find_coins(&self.connection, &outpoints).await?;
+        Ok(coins.values().any(Option::is_some))
+    }
+
+    pub fn new(connection: Arc<Connection>) -> Self {
+        Self { connection }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limit_violation() {
+        let cases = [
+            (
+                "too many unconfirmed ancestors [limit: 25]",
+                LimitViolation::AncestorCount { limit: 25 },
+            ),
+            (
+                "exceeds ancestor size limit [limit: 101000]",
+                LimitViolation::AncestorSize { limit: 101000 },
+            ),
+            (
+                "too many descendants for tx 00ab [limit: 25]",
+                LimitViolation::DescendantCount { limit: 25 },
+            ),
+            (
+                "exceeds descendant size limit for tx 00ab [limit: 101000]",
+                LimitViolation::DescendantSize { limit: 101000 },
+            ),
+            (
+                "too many unconfirmed parents [limit: 1000]",
+                LimitViolation::ParentCount { limit: 1000 },
+            ),
+        ];
+        for (message, expected) in cases {
+            assert_eq!(LimitViolation::parse(message), Some(expected));
         }
+        assert_eq!(
+            LimitViolation::parse("bad-txns-inputs-missingorspent"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_broadcast_error_from_reject_reason() {
+        let classify = |reason: &str| BroadcastError::from_reject_reason(reason.to_string());
+        assert_eq!(
+            classify("txn-already-known"),
+            BroadcastError::AlreadyInMempool
+        );
+        assert_eq!(
+            classify("bad-txns-inputs-missingorspent"),
+            BroadcastError::MissingInputs
+        );
+        assert_eq!(
+            classify("min relay fee not met, 100 < 141"),
+            BroadcastError::InsufficientFee {
+                reason: "min relay fee not met, 100 < 141".to_string()
+            }
+        );
+        assert_eq!(
+            classify("non-final"),
+            BroadcastError::Rejected {
+                reason: "non-final".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_fee_to_sat() {
+        let tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        let rate = FeeRate::from_sat_per_vb_unchecked(2);
+        assert_eq!(MaxFee::Unlimited.to_sat(&tx), 0);
+        assert_eq!(MaxFee::Absolute(Amount::from_sat(500)).to_sat(&tx), 500);
+        assert_eq!(MaxFee::Rate(rate).to_sat(&tx), 2 * tx.vsize() as i64);
     }
 }
@@ -0,0 +1,382 @@
+use bitcoin::consensus::Decodable;
+use bitcoin::hashes::Hash;
+use bitcoin::{block, Amount, Block, BlockHash, Transaction, TxMerkleNode};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::blocks::bytes_to_block_hash;
+use crate::connection::with_thread;
+use crate::error::ChainErrorKind;
+use crate::mining_capnp::block_template::Client as BlockTemplateClient;
+use crate::{BlockTalkError, Connection};
+
+/// Options passed to `createNewBlock`.
+#[derive(Debug, Clone)]
+pub struct BlockCreateOptions {
+    /// Include mempool transactions in the template
+    pub use_mempool: bool,
+    /// Weight reserved for the coinbase transaction and block header
+    pub block_reserved_weight: u64,
+    /// Sigops the coinbase outputs may add on top of the template
+    pub coinbase_output_max_additional_sigops: u64,
+}
+
+impl Default for BlockCreateOptions {
+    fn default() -> Self {
+        Self {
+            use_mempool: true,
+            block_reserved_weight: 8000,
+            coinbase_output_max_additional_sigops: 400,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait MiningInterface: Send + Sync {
+    /// Check if the node is running on a test chain
+    async fn is_test_chain(&self) -> Result<bool, BlockTalkError>;
+
+    /// Check if the node is still in initial block download
+    async fn is_initial_block_download(&self) -> Result<bool, BlockTalkError>;
+
+    /// Get the current tip block's height and hash, if the node has one
+    async fn get_tip(&self) -> Result<Option<(i32, BlockHash)>, BlockTalkError>;
+
+    /// Wait until the tip differs from `current_tip` or `timeout` expires and
+    /// return the tip at that point
+    async fn wait_tip_changed(
+        &self,
+        current_tip: &BlockHash,
+        timeout: Duration,
+    ) -> Result<(i32, BlockHash), BlockTalkError>;
+
+    /// Create a new block template on top of the current tip
+    async fn create_new_block(
+        &self,
+        options: BlockCreateOptions,
+    ) -> Result<BlockTemplate, BlockTalkError>;
+}
+
+pub struct Mining {
+    connection: Arc<Connection>,
+}
+
+#[async_trait::async_trait]
+impl MiningInterface for Mining {
+    async fn is_test_chain(&self) -> Result<bool, BlockTalkError> {
+        log::debug!("Checking if node is on a test chain");
+        self.connection
+            .run(|clients| async move {
+                let req = with_thread!(clients.mining.is_test_chain_request(), clients.thread);
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to check test chain: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }
+
+    async fn is_initial_block_download(&self) -> Result<bool, BlockTalkError> {
+        log::debug!("Checking if node is in initial block download");
+        self.connection
+            .run(|clients| async move {
+                let req = with_thread!(
+                    clients.mining.is_initial_block_download_request(),
+                    clients.thread
+                );
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to check initial block download: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }
+
+    async fn get_tip(&self) -> Result<Option<(i32, BlockHash)>, BlockTalkError> {
+        log::debug!("Fetching mining tip");
+        let tip = self
+            .connection
+            .run(|clients| async move {
+                let req = with_thread!(clients.mining.get_tip_request(), clients.thread);
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get mining tip: {}", e);
+                    BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+                })?;
+
+                let result = response.get()?;
+                if !result.get_has_result() {
+                    return Ok(None);
+                }
+                let tip = result.get_result()?;
+                Ok(Some((tip.get_height(), tip.get_hash()?.to_vec())))
+            })
+            .await?;
+
+        match tip {
+            Some((height, hash)) => Ok(Some((height, bytes_to_block_hash(&hash)?))),
+            None => Ok(None),
+        }
+    }
+
+    async fn wait_tip_changed(
+        &self,
+        current_tip: &BlockHash,
+        timeout: Duration,
+    ) -> Result<(i32, BlockHash), BlockTalkError> {
+        log::debug!("Waiting for tip to change from {}", current_tip);
+        let current_tip = *current_tip;
+        let (height, hash) = self
+            .connection
+            .run(move |clients| async move {
+                // The node serves calls of a thread one at a time, so a long
+                // wait gets its own thread instead of blocking the shared one.
+                let thread = clients.make_thread().await?;
+                let mut req = with_thread!(clients.mining.wait_tip_changed_request(), thread);
+
+                let mut params = req.get();
+                params.set_current_tip(current_tip.as_ref());
+                params.set_timeout(timeout.as_secs_f64() * 1000.0);
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to wait for tip change: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let tip = response.get()?.get_result()?;
+                Ok((tip.get_height(), tip.get_hash()?.to_vec()))
+            })
+            .await?;
+
+        Ok((height, bytes_to_block_hash(&hash)?))
+    }
+
+    async fn create_new_block(
+        &self,
+        options: BlockCreateOptions,
+    ) -> Result<BlockTemplate, BlockTalkError> {
+        log::debug!("Creating new block template");
+        let id = self
+            .connection
+            .run(move |clients| async move {
+                let mut req = clients.mining.create_new_block_request();
+
+                let mut params = req.get().init_options();
+                params.set_use_mempool(options.use_mempool);
+                params.set_block_reserved_weight(options.block_reserved_weight);
+                params.set_coinbase_output_max_additional_sigops(
+                    options.coinbase_output_max_additional_sigops,
+                );
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to create block template: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let template: BlockTemplateClient = response.get()?.get_result()?;
+                Ok(clients.capabilities.insert(template))
+            })
+            .await?;
+
+        log::debug!("Created block template {}", id);
+        Ok(BlockTemplate {
+            connection: self.connection.clone(),
+            id,
+        })
+    }
+}
+
+impl Mining {
+    pub fn new(connection: Arc<Connection>) -> Self {
+        Self { connection }
+    }
+}
+
+/// Block template created by the node.
+///
+/// The template is kept alive on the node until this handle is dropped. It
+/// belongs to the session it was created in and becomes unusable after a
+/// reconnect.
+pub struct BlockTemplate {
+    connection: Arc<Connection>,
+    id: u64,
+}
+
+/// Run a `BlockTemplate` method with its context set to the session thread.
+macro_rules! template_call {
+    ($self:ident, $request:ident, |$params:ident| $setup:expr, |$result:ident| $extract:expr) => {{
+        let id = $self.id;
+        $self
+            .connection
+            .run(move |clients| async move {
+                let template: BlockTemplateClient =
+                    clients.capabilities.get(id).ok_or_else(|| {
+                        log::error!("Block template {} is no longer available", id);
+                        BlockTalkError::Connection(format!(
+                            "Block template {} is no longer available",
+                            id
+                        ))
+                    })?;
+                let mut req = with_thread!(template.$request(), clients.thread);
+
+                {
+                    #[allow(unused_mut, unused_variables)]
+                    let mut $params = req.get();
+                    $setup;
+                }
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Block template call failed: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                let $result = response.get()?;
+                $extract
+            })
+            .await
+    }};
+}
+
+impl BlockTemplate {
+    pub async fn header(&self) -> Result<block::Header, BlockTalkError> {
+        let data = template_call!(self, get_block_header_request, |params| (), |result| {
+            Ok(result.get_result()?.to_vec())
+        })?;
+        decode(&data, "block header")
+    }
+
+    pub async fn block(&self) -> Result<Block, BlockTalkError> {
+        let data = template_call!(self, get_block_request, |params| (), |result| {
+            Ok(result.get_result()?.to_vec())
+        })?;
+        decode(&data, "block")
+    }
+
+    /// Fees of each non-coinbase transaction, in block order
+    pub async fn tx_fees(&self) -> Result<Vec<Amount>, BlockTalkError> {
+        let fees = template_call!(self, get_tx_fees_request, |params| (), |result| {
+            Ok(result.get_result()?.iter().collect::<Vec<i64>>())
+        })?;
+        fees.into_iter()
+            .map(|fee| {
+                u64::try_from(fee).map(Amount::from_sat).map_err(|_| {
+                    BlockTalkError::chain_error(
+                        ChainErrorKind::InvalidBlockData,
+                        format!("Negative transaction fee in template: {}", fee),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Signature operation cost of each non-coinbase transaction, in block order
+    pub async fn tx_sigops(&self) -> Result<Vec<i64>, BlockTalkError> {
+        template_call!(self, get_tx_sigops_request, |params| (), |result| {
+            Ok(result.get_result()?.iter().collect())
+        })
+    }
+
+    pub async fn coinbase_tx(&self) -> Result<Transaction, BlockTalkError> {
+        let data = template_call!(self, get_coinbase_tx_request, |params| (), |result| {
+            Ok(result.get_result()?.to_vec())
+        })?;
+        decode(&data, "coinbase transaction")
+    }
+
+    /// Serialized witness commitment output script
+    pub async fn coinbase_commitment(&self) -> Result<Vec<u8>, BlockTalkError> {
+        template_call!(
+            self,
+            get_coinbase_commitment_request,
+            |params| (),
+            |result| { Ok(result.get_result()?.to_vec()) }
+        )
+    }
+
+    /// Index of the witness commitment output in the coinbase, if any
+    pub async fn witness_commitment_index(&self) -> Result<Option<usize>, BlockTalkError> {
+        let index = template_call!(
+            self,
+            get_witness_commitment_index_request,
+            |params| (),
+            |result| Ok(result.get_result())
+        )?;
+        Ok(usize::try_from(index).ok())
+    }
+
+    /// Merkle branch linking the coinbase transaction to the merkle root
+    pub async fn coinbase_merkle_path(&self) -> Result<Vec<TxMerkleNode>, BlockTalkError> {
+        let path = template_call!(
+            self,
+            get_coinbase_merkle_path_request,
+            |params| (),
+            |result| {
+                result
+                    .get_result()?
+                    .iter()
+                    .map(|node| Ok(node?.to_vec()))
+                    .collect::<Result<Vec<_>, BlockTalkError>>()
+            }
+        )?;
+        path.iter()
+            .map(|node| {
+                TxMerkleNode::from_slice(node).map_err(|e| {
+                    log::error!("Invalid merkle path node: {}", e);
+                    BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
+                })
+            })
+            .collect()
+    }
+
+    /// Submit a solved block built from this template. Returns whether the
+    /// node accepted it.
+    pub async fn submit_solution(
+        &self,
+        version: u32,
+        timestamp: u32,
+        nonce: u32,
+        coinbase: &Transaction,
+    ) -> Result<bool, BlockTalkError> {
+        log::debug!("Submitting solution for block template {}", self.id);
+        let coinbase = bitcoin::consensus::serialize(coinbase);
+        template_call!(
+            self,
+            submit_solution_request,
+            |params| {
+                params.set_version(version);
+                params.set_timestamp(timestamp);
+                params.set_nonce(nonce);
+                params.set_coinbase(coinbase.as_slice());
+            },
+            |result| Ok(result.get_result())
+        )
+    }
+}
+
+impl Drop for BlockTemplate {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.connection.run_detached(move |clients| async move {
+            let Some(template) = clients.capabilities.remove::<BlockTemplateClient>(id) else {
+                return Ok(());
+            };
+            let req = with_thread!(template.destroy_request(), clients.thread);
+            req.send().promise.await?;
+            log::debug!("Destroyed block template {}", id);
+            Ok(())
+        });
+    }
+}
+
+fn decode<T: Decodable>(data: &[u8], what: &str) -> Result<T, BlockTalkError> {
+    bitcoin::consensus::deserialize(data).map_err(|e| {
+        log::error!("Failed to decode template {}: {}", what, e);
+        BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+    })
+}
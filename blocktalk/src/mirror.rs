@@ -0,0 +1,648 @@
+use bitcoin::{Amount, Block, FeeRate, OutPoint, Transaction, Txid, Weight};
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::task::JoinHandle;
+
+use crate::stream::{NotificationStream, StreamItem, SubscribeOptions};
+use crate::{BlockTalkError, ChainInterface, ChainNotification, MempoolInterface};
+
+/// A transaction in the mirrored mempool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MirrorEntry {
+    pub tx: Transaction,
+    /// Fee paid, `None` if a spent output could not be found
+    pub fee: Option<Amount>,
+    pub weight: Weight,
+    /// In-mempool transactions this one spends from
+    pub parents: HashSet<Txid>,
+    /// In-mempool transactions spending from this one
+    pub children: HashSet<Txid>,
+}
+
+impl MirrorEntry {
+    pub fn fee_rate(&self) -> Option<FeeRate> {
+        self.fee.map(|fee| fee_rate(fee, self.weight))
+    }
+}
+
+/// Transactions whose fee rate is at least `fee_rate` and below the next
+/// bucket's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeHistogramBucket {
+    pub fee_rate: FeeRate,
+    pub count: usize,
+    pub weight: Weight,
+    pub fees: Amount,
+}
+
+/// Transactions a miner would likely include next, in block order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProjectedBlock {
+    pub txids: Vec<Txid>,
+    pub weight: Weight,
+    pub fees: Amount,
+}
+
+fn fee_rate(fee: Amount, weight: Weight) -> FeeRate {
+    let wu = weight.to_wu().max(1);
+    FeeRate::from_sat_per_kwu(fee.to_sat().saturating_mul(1000) / wu)
+}
+
+/// Mempool transactions and the spend graph between them.
+#[derive(Debug, Default)]
+pub(crate) struct MirrorState {
+    entries: HashMap<Txid, MirrorEntry>,
+    /// Outpoints spent by mempool transactions
+    spends: HashMap<OutPoint, Txid>,
+}
+
+impl MirrorState {
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.spends.clear();
+    }
+
+    /// Add `tx` without a fee, linking it to in-mempool parents and children.
+    /// Returns the transactions whose fee needs resolving: `tx` followed by
+    /// any children that arrived before it, or nothing if it was already
+    /// present.
+    pub(crate) fn insert(&mut self, tx: Transaction) -> Vec<Txid> {
+        let txid = tx.compute_txid();
+        if self.entries.contains_key(&txid) {
+            return Vec::new();
+        }
+
+        let mut parents = HashSet::new();
+        for input in &tx.input {
+            self.spends.insert(input.previous_output, txid);
+            let parent = input.previous_output.txid;
+            if let Some(entry) = self.entries.get_mut(&parent) {
+                entry.children.insert(txid);
+                parents.insert(parent);
+            }
+        }
+
+        // Children seen before their parent, e.g. while resyncing
+        let mut children = HashSet::new();
+        for vout in 0..tx.output.len() as u32 {
+            if let Some(child) = self.spends.get(&OutPoint::new(txid, vout)).copied() {
+                if let Some(entry) = self.entries.get_mut(&child) {
+                    entry.parents.insert(txid);
+                    children.insert(child);
+                }
+            }
+        }
+
+        let mut unresolved = vec![txid];
+        unresolved.extend(children.iter().copied());
+        let weight = tx.weight();
+        self.entries.insert(
+            txid,
+            MirrorEntry {
+                tx,
+                fee: None,
+                weight,
+                parents,
+                children,
+            },
+        );
+        unresolved
+    }
+
+    /// Remove a single transaction, leaving its descendants in place.
+    pub(crate) fn remove(&mut self, txid: &Txid) -> Option<MirrorEntry> {
+        let entry = self.entries.remove(txid)?;
+        for input in &entry.tx.input {
+            if self.spends.get(&input.previous_output) == Some(txid) {
+                self.spends.remove(&input.previous_output);
+            }
+        }
+        for parent in &entry.parents {
+            if let Some(parent) = self.entries.get_mut(parent) {
+                parent.children.remove(txid);
+            }
+        }
+        for child in &entry.children {
+            if let Some(child) = self.entries.get_mut(child) {
+                child.parents.remove(txid);
+            }
+        }
+        Some(entry)
+    }
+
+    /// Remove the transactions confirmed by `block` and everything
+    /// conflicting with them.
+    pub(crate) fn remove_for_block(&mut self, block: &Block) {
+        let confirmed: HashSet<Txid> = block.txdata.iter().map(|tx| tx.compute_txid()).collect();
+        let mut conflicts = Vec::new();
+        for tx in &block.txdata {
+            for input in &tx.input {
+                if let Some(spender) = self.spends.get(&input.previous_output) {
+                    if !confirmed.contains(spender) {
+                        conflicts.push(*spender);
+                    }
+                }
+            }
+        }
+        for txid in &confirmed {
+            self.remove(txid);
+        }
+        for txid in conflicts {
+            for descendant in self.descendants(&txid) {
+                self.remove(&descendant);
+            }
+            self.remove(&txid);
+        }
+    }
+
+    /// Outpoints spent by `txids` that are not outputs of mirrored
+    /// transactions.
+    pub(crate) fn external_prevouts(&self, txids: &[Txid]) -> Vec<OutPoint> {
+        txids
+            .iter()
+            .filter_map(|txid| self.entries.get(txid))
+            .flat_map(|entry| entry.tx.input.iter().map(|input| input.previous_output))
+            .filter(|outpoint| !self.entries.contains_key(&outpoint.txid))
+            .collect()
+    }
+
+    /// Compute the fee of `txids` from mirrored parents and `coins`.
+    pub(crate) fn resolve_fees(&mut self, txids: &[Txid], coins: &HashMap<OutPoint, Amount>) {
+        for txid in txids {
+            let Some(entry) = self.entries.get(txid) else {
+                continue;
+            };
+            let mut input_value = Amount::ZERO;
+            let mut complete = true;
+            for input in &entry.tx.input {
+                let prevout = input.previous_output;
+                let value = match self.entries.get(&prevout.txid) {
+                    Some(parent) => parent
+                        .tx
+                        .output
+                        .get(prevout.vout as usize)
+                        .map(|output| output.value),
+                    None => coins.get(&prevout).copied(),
+                };
+                match value {
+                    Some(value) => input_value += value,
+                    None => complete = false,
+                }
+            }
+            let output_value: Amount = entry.tx.output.iter().map(|output| output.value).sum();
+            let fee = if complete {
+                input_value.checked_sub(output_value)
+            } else {
+                None
+            };
+            if let Some(entry) = self.entries.get_mut(txid) {
+                entry.fee = fee;
+            }
+        }
+    }
+
+    fn walk(&self, txid: &Txid, next: impl Fn(&MirrorEntry) -> &HashSet<Txid>) -> Vec<Txid> {
+        let mut seen = HashSet::new();
+        let mut pending: Vec<Txid> = self
+            .entries
+            .get(txid)
+            .map_or_else(Vec::new, |entry| next(entry).iter().copied().collect());
+        while let Some(current) = pending.pop() {
+            if !seen.insert(current) {
+                continue;
+            }
+            if let Some(entry) = self.entries.get(&current) {
+                pending.extend(next(entry).iter().copied());
+            }
+        }
+        seen.into_iter().collect()
+    }
+
+    pub(crate) fn ancestors(&self, txid: &Txid) -> Vec<Txid> {
+        self.walk(txid, |entry| &entry.parents)
+    }
+
+    pub(crate) fn descendants(&self, txid: &Txid) -> Vec<Txid> {
+        self.walk(txid, |entry| &entry.children)
+    }
+
+    pub(crate) fn conflicts(&self, tx: &Transaction) -> Vec<Txid> {
+        let txid = tx.compute_txid();
+        let conflicts: HashSet<Txid> = tx
+            .input
+            .iter()
+            .filter_map(|input| self.spends.get(&input.previous_output))
+            .filter(|spender| **spender != txid)
+            .copied()
+            .collect();
+        conflicts.into_iter().collect()
+    }
+
+    pub(crate) fn fee_histogram(&self, bounds: &[FeeRate]) -> Vec<FeeHistogramBucket> {
+        let mut buckets: Vec<FeeHistogramBucket> = bounds
+            .iter()
+            .map(|fee_rate| FeeHistogramBucket {
+                fee_rate: *fee_rate,
+                count: 0,
+                weight: Weight::ZERO,
+                fees: Amount::ZERO,
+            })
+            .collect();
+        buckets.sort_by_key(|bucket| bucket.fee_rate);
+
+        for entry in self.entries.values() {
+            let (Some(fee), Some(rate)) = (entry.fee, entry.fee_rate()) else {
+                continue;
+            };
+            if let Some(bucket) = buckets.iter_mut().rev().find(|b| b.fee_rate <= rate) {
+                bucket.count += 1;
+                bucket.weight += entry.weight;
+                bucket.fees += fee;
+            }
+        }
+        buckets
+    }
+
+    /// Greedy selection by ancestor package fee rate, the way block
+    /// assembly prefers CPFP packages. Package rates are not updated as
+    /// ancestors get included, so this is an estimate.
+    pub(crate) fn projected_block(&self, max_weight: Weight) -> ProjectedBlock {
+        let mut candidates = Vec::new();
+        for (txid, entry) in &self.entries {
+            let ancestors = self.ancestors(txid);
+            let mut fee = entry.fee;
+            let mut weight = entry.weight;
+            for ancestor in &ancestors {
+                let ancestor = &self.entries[ancestor];
+                fee = fee.zip(ancestor.fee).map(|(a, b)| a + b);
+                weight += ancestor.weight;
+            }
+            if let Some(fee) = fee {
+                candidates.push((fee_rate(fee, weight), *txid, ancestors));
+            }
+        }
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let mut block = ProjectedBlock::default();
+        let mut included = HashSet::new();
+        for (_, txid, ancestors) in candidates {
+            if included.contains(&txid) {
+                continue;
+            }
+            let mut package: Vec<Txid> = ancestors
+                .into_iter()
+                .filter(|ancestor| !included.contains(ancestor))
+                .collect();
+            // Parents have fewer ancestors than their children
+            package.sort_by_cached_key(|ancestor| self.ancestors(ancestor).len());
+            package.push(txid);
+
+            let weight = package
+                .iter()
+                .fold(Weight::ZERO, |sum, txid| sum + self.entries[txid].weight);
+            if block.weight + weight > max_weight {
+                continue;
+            }
+            for txid in package {
+                let entry = &self.entries[&txid];
+                block.weight += entry.weight;
+                block.fees += entry.fee.unwrap_or(Amount::ZERO);
+                included.insert(txid);
+                block.txids.push(txid);
+            }
+        }
+        block
+    }
+}
+
+/// In-memory copy of the node's mempool.
+///
+/// Bootstrapped from `MempoolInterface::snapshot` and kept up to date from
+/// chain notifications by a background task, so fee and ancestry queries
+/// are answered locally. Dropping the mirror stops the task.
+pub struct MempoolMirror {
+    state: Arc<Mutex<MirrorState>>,
+    task: JoinHandle<()>,
+}
+
+impl MempoolMirror {
+    /// Subscribe to notifications, load the current mempool and start
+    /// mirroring it.
+    pub async fn start(
+        chain: Arc<dyn ChainInterface>,
+        mempool: Arc<dyn MempoolInterface>,
+    ) -> Result<Self, BlockTalkError> {
+        log::info!("Starting mempool mirror");
+        // Subscribe first so nothing between the snapshot and the first
+        // notification is missed.
+        let stream = chain.subscribe(SubscribeOptions::default()).await?;
+        let state = Arc::new(Mutex::new(MirrorState::default()));
+        resync(chain.as_ref(), mempool.as_ref(), &state).await?;
+
+        let task = tokio::spawn(follow(chain, mempool, state.clone(), stream));
+        Ok(Self { state, task })
+    }
+
+    fn state(&self) -> MutexGuard<'_, MirrorState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn len(&self) -> usize {
+        self.state().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state().entries.is_empty()
+    }
+
+    pub fn contains(&self, txid: &Txid) -> bool {
+        self.state().entries.contains_key(txid)
+    }
+
+    pub fn get(&self, txid: &Txid) -> Option<MirrorEntry> {
+        self.state().entries.get(txid).cloned()
+    }
+
+    /// In-mempool ancestors of `txid`, in no particular order
+    pub fn ancestors(&self, txid: &Txid) -> Vec<Txid> {
+        self.state().ancestors(txid)
+    }
+
+    /// In-mempool descendants of `txid`, in no particular order
+    pub fn descendants(&self, txid: &Txid) -> Vec<Txid> {
+        self.state().descendants(txid)
+    }
+
+    /// Mempool transactions spending any input of `tx`
+    pub fn conflicts(&self, tx: &Transaction) -> Vec<Txid> {
+        self.state().conflicts(tx)
+    }
+
+    /// Count, weight and fees of transactions per fee rate bucket, one
+    /// bucket per lower bound in `bounds`. Transactions below the lowest
+    /// bound or with an unknown fee are left out
+    pub fn fee_histogram(&self, bounds: &[FeeRate]) -> Vec<FeeHistogramBucket> {
+        self.state().fee_histogram(bounds)
+    }
+
+    /// Estimate the next block's transactions up to `max_weight`
+    pub fn projected_block(&self, max_weight: Weight) -> ProjectedBlock {
+        self.state().projected_block(max_weight)
+    }
+}
+
+impl Drop for MempoolMirror {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Replace the mirror's contents with the node's current mempool.
+async fn resync(
+    chain: &dyn ChainInterface,
+    mempool: &dyn MempoolInterface,
+    state: &Mutex<MirrorState>,
+) -> Result<(), BlockTalkError> {
+    let txs = mempool.snapshot().await?;
+    let txids: Vec<Txid> = txs.iter().map(|tx| tx.compute_txid()).collect();
+    {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.clear();
+        for tx in txs {
+            state.insert(tx);
+        }
+    }
+    resolve_fees(chain, state, &txids).await?;
+    log::info!("Mempool mirror loaded {} transactions", txids.len());
+    Ok(())
+}
+
+/// Look up the coins spent by `txids` outside the mirror and set their fees.
+async fn resolve_fees(
+    chain: &dyn ChainInterface,
+    state: &Mutex<MirrorState>,
+    txids: &[Txid],
+) -> Result<(), BlockTalkError> {
+    let outpoints = state
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .external_prevouts(txids);
+    let coins: HashMap<OutPoint, Amount> = if outpoints.is_empty() {
+        HashMap::new()
+    } else {
+        chain
+            .find_coins(&outpoints)
+            .await?
+            .into_iter()
+            .filter_map(|(outpoint, coin)| Some((outpoint, coin?.output.value)))
+            .collect()
+    };
+    state
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .resolve_fees(txids, &coins);
+    Ok(())
+}
+
+async fn follow(
+    chain: Arc<dyn ChainInterface>,
+    mempool: Arc<dyn MempoolInterface>,
+    state: Arc<Mutex<MirrorState>>,
+    mut stream: NotificationStream,
+) {
+    while let Some(item) = stream.next().await {
+        let notification = match item {
+            StreamItem::Notification(notification) => notification,
+            StreamItem::Lagged(missed) => {
+                log::warn!("Mempool mirror missed {} notifications, reloading", missed);
+                if let Err(e) = resync(chain.as_ref(), mempool.as_ref(), &state).await {
+                    log::error!("Failed to update mempool mirror: {}", e);
+                }
+                continue;
+            }
+        };
+        let result = match notification {
+            ChainNotification::TransactionAddedToMempool(tx) => {
+                let txids = state.lock().unwrap_or_else(|e| e.into_inner()).insert(tx);
+                if txids.is_empty() {
+                    Ok(())
+                } else {
+                    resolve_fees(chain.as_ref(), &state, &txids).await
+                }
+            }
+            ChainNotification::TransactionRemovedFromMempool { tx, .. } => {
+                let txid = tx.compute_txid();
+                state
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&txid);
+                Ok(())
+            }
+            ChainNotification::BlockConnected { block, .. } => {
+                state
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove_for_block(&block.block);
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            log::error!("Failed to update mempool mirror: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{absolute, transaction, ScriptBuf, Sequence, TxIn, TxOut, Witness};
+
+    fn tx(inputs: &[OutPoint], outputs: &[u64]) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|previous_output| TxIn {
+                    previous_output: *previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|value| TxOut {
+                    value: Amount::from_sat(*value),
+                    script_pubkey: ScriptBuf::new(),
+                })
+                .collect(),
+        }
+    }
+
+    fn confirmed(n: u8) -> OutPoint {
+        OutPoint::new(Txid::from_byte_array([n; 32]), 0)
+    }
+
+    /// Insert `txs` and resolve fees with every confirmed coin worth 10_000
+    fn state(txs: &[Transaction]) -> MirrorState {
+        let mut state = MirrorState::default();
+        let txids: Vec<Txid> = txs.iter().map(|tx| tx.compute_txid()).collect();
+        for tx in txs {
+            state.insert(tx.clone());
+        }
+        let coins = state
+            .external_prevouts(&txids)
+            .into_iter()
+            .map(|outpoint| (outpoint, Amount::from_sat(10_000)))
+            .collect();
+        state.resolve_fees(&txids, &coins);
+        state
+    }
+
+    #[test]
+    fn test_graph_and_fees() {
+        let parent = tx(&[confirmed(1)], &[9_000]);
+        let child = tx(&[OutPoint::new(parent.compute_txid(), 0)], &[8_500]);
+        // Child first, as can happen while resyncing
+        let state = state(&[child.clone(), parent.clone()]);
+
+        let entry = &state.entries[&child.compute_txid()];
+        assert_eq!(entry.fee, Some(Amount::from_sat(500)));
+        assert_eq!(
+            state.ancestors(&child.compute_txid()),
+            vec![parent.compute_txid()]
+        );
+        assert_eq!(
+            state.descendants(&parent.compute_txid()),
+            vec![child.compute_txid()]
+        );
+    }
+
+    #[test]
+    fn test_parent_after_child_resolves_child_fee() {
+        let parent = tx(&[confirmed(1)], &[9_000]);
+        let child = tx(&[OutPoint::new(parent.compute_txid(), 0)], &[8_500]);
+        let coins = HashMap::from([(confirmed(1), Amount::from_sat(10_000))]);
+        let mut state = MirrorState::default();
+
+        let txids = state.insert(child.clone());
+        state.resolve_fees(&txids, &coins);
+        assert_eq!(state.entries[&child.compute_txid()].fee, None);
+
+        let txids = state.insert(parent.clone());
+        assert_eq!(txids, vec![parent.compute_txid(), child.compute_txid()]);
+        state.resolve_fees(&txids, &coins);
+        assert_eq!(
+            state.entries[&parent.compute_txid()].fee,
+            Some(Amount::from_sat(1_000))
+        );
+        assert_eq!(
+            state.entries[&child.compute_txid()].fee,
+            Some(Amount::from_sat(500))
+        );
+        assert!(state.insert(parent).is_empty());
+    }
+
+    #[test]
+    fn test_remove_for_block_evicts_conflicts() {
+        let spend = tx(&[confirmed(1)], &[9_000]);
+        let child = tx(&[OutPoint::new(spend.compute_txid(), 0)], &[8_000]);
+        let other = tx(&[confirmed(2)], &[9_000]);
+        let mut state = state(&[spend.clone(), child, other.clone()]);
+
+        let double_spend = tx(&[confirmed(1)], &[5_000]);
+        assert_eq!(state.conflicts(&double_spend), vec![spend.compute_txid()]);
+
+        let block = Block {
+            header: bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest).header,
+            txdata: vec![double_spend],
+        };
+        state.remove_for_block(&block);
+        assert_eq!(state.entries.len(), 1);
+        assert!(state.entries.contains_key(&other.compute_txid()));
+        assert_eq!(state.spends.len(), 1);
+    }
+
+    #[test]
+    fn test_fee_histogram() {
+        let low = tx(&[confirmed(1)], &[9_900]);
+        let high = tx(&[confirmed(2)], &[5_000]);
+        let state = state(&[low, high.clone()]);
+
+        let bounds = [
+            FeeRate::from_sat_per_vb_unchecked(1),
+            FeeRate::from_sat_per_vb_unchecked(10),
+        ];
+        let histogram = state.fee_histogram(&bounds);
+        assert_eq!(histogram[0].count, 1);
+        assert_eq!(histogram[1].count, 1);
+        assert_eq!(histogram[1].fees, Amount::from_sat(5_000));
+        assert_eq!(histogram[1].weight, high.weight());
+    }
+
+    #[test]
+    fn test_projected_block_prefers_cpfp_package() {
+        let parent = tx(&[confirmed(1)], &[9_990]);
+        let child = tx(&[OutPoint::new(parent.compute_txid(), 0)], &[4_000]);
+        let other = tx(&[confirmed(2)], &[9_000]);
+        let state = state(&[parent.clone(), child.clone(), other.clone()]);
+
+        let block = state.projected_block(Weight::MAX_BLOCK);
+        assert_eq!(
+            block.txids,
+            vec![
+                parent.compute_txid(),
+                child.compute_txid(),
+                other.compute_txid()
+            ]
+        );
+        assert_eq!(block.fees, Amount::from_sat(7_000));
+
+        let room_for_one = state.projected_block(other.weight());
+        assert_eq!(room_for_one.txids, vec![other.compute_txid()]);
+    }
+}
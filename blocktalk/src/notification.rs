@@ -1,25 +1,164 @@
 use async_trait::async_trait;
 use bitcoin::hashes::Hash;
-use bitcoin::{consensus::Decodable, Block, BlockHash, Transaction, Txid};
+use bitcoin::{consensus::Decodable, Block, BlockHash, Transaction};
 use capnp::capability::Promise;
-use capnp_rpc::pry;
+use futures::FutureExt;
+use std::any::Any;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use crate::chain_capnp::chain_notifications;
-use crate::error::BlockTalkError;
+use crate::blocks::bytes_to_block_hash;
+use crate::chain_capnp::{block_info, chain_notifications};
+use crate::coin::{decode_block_undo, TxUndo};
+use crate::connection::with_thread;
+use crate::error::{BlockTalkError, ChainErrorKind};
+use crate::RpcClients;
 
 // Public interface
 #[derive(Clone, Debug)]
 pub enum ChainNotification {
-    BlockConnected(Block),
-    BlockDisconnected(BlockHash),
+    BlockConnected {
+        role: ChainstateRole,
+        block: BlockInfo,
+    },
+    BlockDisconnected {
+        block: BlockInfo,
+    },
     TransactionAddedToMempool(Transaction),
-    TransactionRemovedFromMempool(Txid),
-    UpdatedBlockTip(BlockHash),
+    TransactionRemovedFromMempool {
+        tx: Transaction,
+        reason: MempoolRemovalReason,
+    },
+    /// The active chain tip changed. Carries the tip as seen by the node when
+    /// the notification was processed.
+    UpdatedBlockTip {
+        hash: BlockHash,
+        height: i32,
+        initial_block_download: bool,
+    },
     ChainStateFlushed,
 }
 
+/// Block carried by `BlockConnected` and `BlockDisconnected` notifications.
+#[derive(Clone, Debug)]
+pub struct BlockInfo {
+    pub hash: BlockHash,
+    /// `None` for the genesis block
+    pub prev_hash: Option<BlockHash>,
+    pub height: i32,
+    pub block: Block,
+    /// Coins spent by the block's non-coinbase transactions, when the node
+    /// includes undo data
+    pub undo: Option<Vec<TxUndo>>,
+    /// Maximum block time of the chain up to and including this block
+    pub chain_time_max: u32,
+}
+
+impl BlockInfo {
+    fn decode(reader: block_info::Reader) -> Result<Self, BlockTalkError> {
+        let block = Block::consensus_decode(&mut reader.get_data()?).map_err(|e| {
+            log::error!("Failed to decode block: {}", e);
+            BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+        })?;
+
+        let hash = match reader.get_hash()? {
+            [] => block.block_hash(),
+            bytes => bytes_to_block_hash(bytes)?,
+        };
+        let prev_hash = match reader.get_prev_hash()? {
+            [] => None,
+            bytes => {
+                Some(bytes_to_block_hash(bytes)?).filter(|hash| *hash != BlockHash::all_zeros())
+            }
+        };
+        let undo = match reader.get_undo_data()? {
+            [] => None,
+            bytes => Some(decode_block_undo(bytes)?),
+        };
+
+        Ok(Self {
+            hash,
+            prev_hash,
+            height: reader.get_height(),
+            block,
+            undo,
+            chain_time_max: reader.get_chain_time_max(),
+        })
+    }
+}
+
+/// Chainstate a block was connected to. Nodes loaded from an assumeutxo
+/// snapshot validate the historical chain in a background chainstate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChainstateRole {
+    /// The only chainstate, no snapshot in use
+    Normal,
+    /// The snapshot chainstate following the network tip
+    AssumedValid,
+    /// The chainstate validating history up to the snapshot
+    Background,
+}
+
+impl TryFrom<u32> for ChainstateRole {
+    type Error = BlockTalkError;
+
+    fn try_from(role: u32) -> Result<Self, Self::Error> {
+        match role {
+            0 => Ok(Self::Normal),
+            1 => Ok(Self::AssumedValid),
+            2 => Ok(Self::Background),
+            _ => Err(BlockTalkError::chain_error(
+                ChainErrorKind::DeserializationFailed,
+                format!("Unknown chainstate role: {}", role),
+            )),
+        }
+    }
+}
+
+/// Why a transaction left the mempool, mirroring Core's `MemPoolRemovalReason`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MempoolRemovalReason {
+    /// Expired from the mempool
+    Expiry,
+    /// Evicted to keep the mempool under its size limit
+    SizeLimit,
+    /// Removed during a reorg
+    Reorg,
+    /// Included in a connected block
+    Block,
+    /// Conflicted with a transaction in a connected block
+    Conflict,
+    /// Replaced by a fee-bumping transaction
+    Replaced,
+    /// A reason added to the node after this version, with its raw code
+    Unknown(i32),
+}
+
+impl From<i32> for MempoolRemovalReason {
+    fn from(reason: i32) -> Self {
+        match reason {
+            0 => Self::Expiry,
+            1 => Self::SizeLimit,
+            2 => Self::Reorg,
+            3 => Self::Block,
+            4 => Self::Conflict,
+            5 => Self::Replaced,
+            _ => {
+                log::warn!("Unknown mempool removal reason: {}", reason);
+                Self::Unknown(reason)
+            }
+        }
+    }
+}
+
+/// Token returned when registering a `NotificationHandler`, used to remove it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HandlerId(u64);
+
 #[async_trait]
 pub trait NotificationHandler: Send + Sync {
     async fn handle_notification(
@@ -28,32 +167,188 @@ pub trait NotificationHandler: Send + Sync {
     ) -> Result<(), BlockTalkError>;
 }
 
+/// How `ChainNotificationHandler` runs handlers for a notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DispatchMode {
+    /// One handler after another, in registration order
+    Sequential,
+    /// All handlers at once
+    Concurrent,
+}
+
+/// Why a handler did not complete for a notification.
+#[derive(Debug, Clone)]
+pub enum HandlerFailure {
+    Error(BlockTalkError),
+    Panicked(String),
+    TimedOut(Duration),
+}
+
+impl fmt::Display for HandlerFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandlerFailure::Error(e) => write!(f, "failed: {}", e),
+            HandlerFailure::Panicked(message) => write!(f, "panicked: {}", message),
+            HandlerFailure::TimedOut(limit) => write!(f, "timed out after {:?}", limit),
+        }
+    }
+}
+
+/// Callback invoked for every handler failure, in addition to logging.
+pub type HandlerErrorCallback = Arc<dyn Fn(HandlerId, &HandlerFailure) + Send + Sync>;
+
+/// Dispatch settings for a `ChainNotificationHandler`.
+///
+/// Handlers are always isolated from each other: a failing, panicking or
+/// timed out handler is reported and the remaining handlers still receive
+/// the notification. Failures are never reported back to the node, including
+/// a notification that could not be completed (see `HandlerMetrics::missed`).
+#[derive(Clone)]
+pub struct DispatchOptions {
+    pub mode: DispatchMode,
+    /// Time limit for a single handler call, `None` for no limit
+    pub handler_timeout: Option<Duration>,
+    pub on_error: Option<HandlerErrorCallback>,
+}
+
+impl Default for DispatchOptions {
+    fn default() -> Self {
+        Self {
+            mode: DispatchMode::Sequential,
+            handler_timeout: None,
+            on_error: None,
+        }
+    }
+}
+
+/// Per-handler dispatch statistics.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandlerMetrics {
+    pub calls: u64,
+    pub errors: u64,
+    pub panics: u64,
+    pub timeouts: u64,
+    /// Notifications that never reached the handler because the follow-up
+    /// query to the node failed
+    pub missed: u64,
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl HandlerMetrics {
+    pub fn average_latency(&self) -> Duration {
+        match u32::try_from(self.calls) {
+            Ok(0) => Duration::ZERO,
+            Ok(calls) => self.total_latency / calls,
+            Err(_) => Duration::from_secs_f64(self.total_latency.as_secs_f64() / self.calls as f64),
+        }
+    }
+
+    fn record(&mut self, latency: Duration, failure: Option<&HandlerFailure>) {
+        self.calls += 1;
+        self.total_latency += latency;
+        self.max_latency = self.max_latency.max(latency);
+        match failure {
+            Some(HandlerFailure::Error(_)) => self.errors += 1,
+            Some(HandlerFailure::Panicked(_)) => self.panics += 1,
+            Some(HandlerFailure::TimedOut(_)) => self.timeouts += 1,
+            None => {}
+        }
+    }
+}
+
+#[derive(Clone)]
+struct HandlerEntry {
+    id: HandlerId,
+    handler: Arc<dyn NotificationHandler>,
+    metrics: Arc<Mutex<HandlerMetrics>>,
+}
+
 #[derive(Clone)]
 pub struct ChainNotificationHandler {
-    handlers: Arc<Mutex<Vec<Arc<dyn NotificationHandler>>>>,
+    handlers: Arc<Mutex<Vec<HandlerEntry>>>,
+    next_id: Arc<AtomicU64>,
+    options: Arc<Mutex<DispatchOptions>>,
 }
 
 impl ChainNotificationHandler {
     pub fn new() -> Self {
         Self {
             handlers: Arc::new(Mutex::new(Vec::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+            options: Arc::new(Mutex::new(DispatchOptions::default())),
         }
     }
 
     pub async fn register_handler(
         &mut self,
         handler: Arc<dyn NotificationHandler>,
-    ) -> Result<(), BlockTalkError> {
+    ) -> Result<HandlerId, BlockTalkError> {
         let mut guard = self.handlers.lock().map_err(|e| {
             BlockTalkError::Connection(format!(
                 "Failed to acquire lock for registering handler: {}",
                 e
             ))
         })?;
-        guard.push(handler);
+        let id = HandlerId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        guard.push(HandlerEntry {
+            id,
+            handler,
+            metrics: Arc::new(Mutex::new(HandlerMetrics::default())),
+        });
+        Ok(id)
+    }
+
+    /// Remove a handler. Returns `false` if `id` is not registered.
+    pub fn remove_handler(&self, id: HandlerId) -> Result<bool, BlockTalkError> {
+        let mut guard = self.handlers.lock().map_err(|e| {
+            BlockTalkError::Connection(format!(
+                "Failed to acquire lock for removing handler: {}",
+                e
+            ))
+        })?;
+        let len = guard.len();
+        guard.retain(|entry| entry.id != id);
+        Ok(guard.len() != len)
+    }
+
+    pub fn set_dispatch_options(&self, options: DispatchOptions) -> Result<(), BlockTalkError> {
+        let mut guard = self.options.lock().map_err(|e| {
+            BlockTalkError::Connection(format!(
+                "Failed to acquire lock for dispatch options: {}",
+                e
+            ))
+        })?;
+        *guard = options;
         Ok(())
     }
 
+    /// Metrics of every registered handler, in registration order.
+    pub fn metrics(&self) -> Result<Vec<(HandlerId, HandlerMetrics)>, BlockTalkError> {
+        let guard = self.handlers.lock().map_err(|e| {
+            BlockTalkError::Connection(format!("Failed to acquire lock for handler metrics: {}", e))
+        })?;
+        Ok(guard
+            .iter()
+            .map(|entry| {
+                let metrics = entry.metrics.lock().unwrap_or_else(|e| e.into_inner());
+                (entry.id, metrics.clone())
+            })
+            .collect())
+    }
+
+    /// Count a notification that could not be delivered to any handler.
+    fn record_missed(&self) {
+        let guard = self.handlers.lock().unwrap_or_else(|e| e.into_inner());
+        for entry in guard.iter() {
+            entry
+                .metrics
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .missed += 1;
+        }
+    }
+
     async fn dispatch_notification(
         &self,
         notification: ChainNotification,
@@ -67,47 +362,173 @@ impl ChainNotificationHandler {
             })?;
             guard.clone()
         };
+        let options = self
+            .options
+            .lock()
+            .map_err(|e| {
+                BlockTalkError::Connection(format!(
+                    "Failed to acquire lock for dispatch options: {}",
+                    e
+                ))
+            })?
+            .clone();
 
-        for handler in handlers {
-            handler.handle_notification(notification.clone()).await?;
+        match options.mode {
+            DispatchMode::Sequential => {
+                for entry in &handlers {
+                    run_handler(entry, notification.clone(), &options).await;
+                }
+            }
+            DispatchMode::Concurrent => {
+                futures::future::join_all(
+                    handlers
+                        .iter()
+                        .map(|entry| run_handler(entry, notification.clone(), &options)),
+                )
+                .await;
+            }
         }
         Ok(())
     }
 }
 
+/// Run one handler, catching panics and enforcing the timeout, and record
+/// the outcome in its metrics.
+async fn run_handler(
+    entry: &HandlerEntry,
+    notification: ChainNotification,
+    options: &DispatchOptions,
+) {
+    let started = Instant::now();
+    let call = AssertUnwindSafe(entry.handler.handle_notification(notification)).catch_unwind();
+    let outcome = match options.handler_timeout {
+        Some(limit) => tokio::time::timeout(limit, call)
+            .await
+            .map_err(|_| HandlerFailure::TimedOut(limit)),
+        None => Ok(call.await),
+    };
+    let failure = match outcome {
+        Ok(Ok(Ok(()))) => None,
+        Ok(Ok(Err(e))) => Some(HandlerFailure::Error(e)),
+        Ok(Err(panic)) => Some(HandlerFailure::Panicked(panic_message(panic.as_ref()))),
+        Err(timeout) => Some(timeout),
+    };
+
+    entry
+        .metrics
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .record(started.elapsed(), failure.as_ref());
+
+    if let Some(failure) = failure {
+        log::error!("Notification handler {:?} {}", entry.id, failure);
+        if let Some(on_error) = &options.on_error {
+            on_error(entry.id, &failure);
+        }
+    }
+}
+
+pub(crate) fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
 impl Default for ChainNotificationHandler {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl chain_notifications::Server for ChainNotificationHandler {
+/// Server side of a `handleNotifications` subscription. It lives on the RPC
+/// thread for a single session and forwards decoded notifications to the
+/// shared `ChainNotificationHandler`.
+pub(crate) struct NotificationServer {
+    handler: ChainNotificationHandler,
+    clients: RpcClients,
+}
+
+impl NotificationServer {
+    pub(crate) fn new(handler: ChainNotificationHandler, clients: RpcClients) -> Self {
+        Self { handler, clients }
+    }
+}
+
+fn decode_transaction(mut data: &[u8]) -> Result<Transaction, BlockTalkError> {
+    Transaction::consensus_decode(&mut data).map_err(|e| {
+        log::error!("Failed to decode transaction: {}", e);
+        BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+    })
+}
+
+/// Query the node for the current tip and initial block download state.
+async fn query_tip(clients: &RpcClients) -> Result<ChainNotification, BlockTalkError> {
+    let height_req = with_thread!(clients.chain.get_height_request(), clients.thread);
+    let response = height_req.send().promise.await?;
+    let response = response.get()?;
+    if !response.get_has_result() {
+        return Err(BlockTalkError::chain_error(
+            ChainErrorKind::InvalidHeight,
+            "Node has no active chain tip".to_string(),
+        ));
+    }
+    let height = response.get_result();
+
+    let mut hash_req = with_thread!(clients.chain.get_block_hash_request(), clients.thread);
+    hash_req.get().set_height(height);
+    let response = hash_req.send().promise.await?;
+    let hash = bytes_to_block_hash(response.get()?.get_result()?)?;
+
+    let ibd_req = with_thread!(
+        clients.chain.is_initial_block_download_request(),
+        clients.thread
+    );
+    let response = ibd_req.send().promise.await?;
+    let initial_block_download = response.get()?.get_result();
+
+    Ok(ChainNotification::UpdatedBlockTip {
+        hash,
+        height,
+        initial_block_download,
+    })
+}
+
+/// Dispatch a notification decoded from a node callback. A notification that
+/// could not be decoded or dispatched is logged and counted as missed, the
+/// node only ever sees the callback succeed.
+async fn deliver(
+    handler: ChainNotificationHandler,
+    notification: Result<ChainNotification, BlockTalkError>,
+) -> Result<(), ::capnp::Error> {
+    let result = match notification {
+        Ok(notification) => handler.dispatch_notification(notification).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        log::error!("Failed to deliver notification: {}", e);
+        handler.record_missed();
+    }
+    Ok(())
+}
+
+impl chain_notifications::Server for NotificationServer {
     fn block_connected(
         &mut self,
         params: chain_notifications::BlockConnectedParams,
         _: chain_notifications::BlockConnectedResults,
     ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
-        let handler = self.clone();
-
-        let future = async move {
+        let notification = (|| -> Result<ChainNotification, BlockTalkError> {
             let params_reader = params.get()?;
-            let block_info = params_reader.get_block()?;
-            let mut block_data = block_info.get_data()?;
-
-            // Decode the block
-            let block = bitcoin::Block::consensus_decode(&mut block_data)
-                .map_err(|e| ::capnp::Error::failed(format!("Failed to decode block: {}", e)))?;
+            let role = ChainstateRole::try_from(params_reader.get_role())?;
+            let block = BlockInfo::decode(params_reader.get_block()?)?;
+            Ok(ChainNotification::BlockConnected { role, block })
+        })();
 
-            // Dispatch notification
-            handler
-                .dispatch_notification(ChainNotification::BlockConnected(block))
-                .await
-                .map_err(|e| {
-                    ::capnp::Error::failed(format!("Failed to dispatch notification: {}", e))
-                })
-        };
-
-        Promise::from_future(future)
+        Promise::from_future(deliver(self.handler.clone(), notification))
     }
 
     fn block_disconnected(
@@ -115,31 +536,12 @@ impl chain_notifications::Server for ChainNotificationHandler {
         params: chain_notifications::BlockDisconnectedParams,
         _: chain_notifications::BlockDisconnectedResults,
     ) -> Promise<(), ::capnp::Error> {
-        let handler = self.clone();
+        let notification = (|| -> Result<ChainNotification, BlockTalkError> {
+            let block = BlockInfo::decode(params.get()?.get_block()?)?;
+            Ok(ChainNotification::BlockDisconnected { block })
+        })();
 
-        let future = async move {
-            let params_reader = params.get()?;
-            let block_info = params_reader.get_block()?;
-            // let height = block_info.get_height();
-            let hash_data = block_info.get_hash()?;
-
-            // Create BlockHash from sha256d hash
-            let hash = {
-                let hash_obj = bitcoin::hashes::sha256d::Hash::from_slice(hash_data)
-                    .map_err(|e| ::capnp::Error::failed(format!("Invalid block hash: {}", e)))?;
-                bitcoin::BlockHash::from(hash_obj)
-            };
-
-            // Dispatch notification
-            handler
-                .dispatch_notification(ChainNotification::BlockDisconnected(hash))
-                .await
-                .map_err(|e| {
-                    ::capnp::Error::failed(format!("Failed to dispatch notification: {}", e))
-                })
-        };
-
-        Promise::from_future(future)
+        Promise::from_future(deliver(self.handler.clone(), notification))
     }
 
     fn transaction_added_to_mempool(
@@ -147,27 +549,12 @@ impl chain_notifications::Server for ChainNotificationHandler {
         params: chain_notifications::TransactionAddedToMempoolParams,
         _: chain_notifications::TransactionAddedToMempoolResults,
     ) -> Promise<(), ::capnp::Error> {
-        let handler = self.clone();
-
-        let tx =
-            match bitcoin::Transaction::consensus_decode(&mut pry!(pry!(params.get()).get_tx())) {
-                Ok(tx) => tx,
-                Err(e) => {
-                    return Promise::err(::capnp::Error::failed(format!(
-                        "Failed to decode transaction: {}",
-                        e
-                    )))
-                }
-            };
+        let notification = (|| -> Result<ChainNotification, BlockTalkError> {
+            let tx = decode_transaction(params.get()?.get_tx()?)?;
+            Ok(ChainNotification::TransactionAddedToMempool(tx))
+        })();
 
-        Promise::from_future(async move {
-            handler
-                .dispatch_notification(ChainNotification::TransactionAddedToMempool(tx))
-                .await
-                .map_err(|e| {
-                    ::capnp::Error::failed(format!("Failed to dispatch notification: {}", e))
-                })
-        })
+        Promise::from_future(deliver(self.handler.clone(), notification))
     }
 
     fn transaction_removed_from_mempool(
@@ -175,73 +562,27 @@ impl chain_notifications::Server for ChainNotificationHandler {
         params: chain_notifications::TransactionRemovedFromMempoolParams,
         _: chain_notifications::TransactionRemovedFromMempoolResults,
     ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
-        let handler = self.clone();
-
-        let txid = match bitcoin::Txid::consensus_decode(&mut pry!(pry!(params.get()).get_tx())) {
-            Ok(txid) => txid,
-            Err(e) => {
-                return Promise::err(::capnp::Error::failed(format!(
-                    "Failed to decode txid: {}",
-                    e
-                )))
-            }
-        };
-
-        Promise::from_future(async move {
-            handler
-                .dispatch_notification(ChainNotification::TransactionRemovedFromMempool(txid))
-                .await
-                .map_err(|e| {
-                    ::capnp::Error::failed(format!("Failed to dispatch notification: {}", e))
-                })
-        })
+        let notification = (|| -> Result<ChainNotification, BlockTalkError> {
+            let params = params.get()?;
+            let reason = MempoolRemovalReason::from(params.get_reason());
+            let tx = decode_transaction(params.get_tx()?)?;
+            Ok(ChainNotification::TransactionRemovedFromMempool { tx, reason })
+        })();
+
+        Promise::from_future(deliver(self.handler.clone(), notification))
     }
 
-    // fn updated_block_tip(
-    //     &mut self,
-    //     params: chain_notifications::UpdatedBlockTipParams,
-    //     _: chain_notifications::UpdatedBlockTipResults,
-    // ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
-    //     let handler = self.clone();
-
-    //     let context_reader = pry!(pry!(params.get()).get_context());
-    //     // According to the schema, the context reader has the data we need but differently structured
-    //     // We'll need to get the height and hash from appropriate fields based on the context schema
-    //     let block_info = pry!(context_reader.get_block());
-    //     let hash_data = pry!(block_info.get_hash());
-
-    //     let hash = match bitcoin::hashes::sha256d::Hash::from_slice(hash_data) {
-    //         Ok(hash_obj) => bitcoin::BlockHash::from(hash_obj),
-    //         Err(e) => return Promise::err(::capnp::Error::failed(format!("Invalid block hash: {}", e)))
-    //     };
-
-    //     // Convert the async dispatch_notification to a Promise
-    //     Promise::from_future(async move {
-    //         handler.dispatch_notification(ChainNotification::UpdatedBlockTip(hash)).await
-    //             .map_err(|e| ::capnp::Error::failed(format!("Failed to dispatch notification: {}", e)))
-    //     })
-    // }
-
     fn updated_block_tip(
         &mut self,
         _params: chain_notifications::UpdatedBlockTipParams,
         _: chain_notifications::UpdatedBlockTipResults,
     ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
-        let handler = self.clone();
+        let handler = self.handler.clone();
+        let clients = self.clients.clone();
 
         let future = async move {
-            // Simply log that we received the notification
-            log::info!("Block tip updated - details skipped");
-
-            let dummy_hash = bitcoin::BlockHash::all_zeros();
-
-            // Dispatch notification with dummy data
-            handler
-                .dispatch_notification(ChainNotification::UpdatedBlockTip(dummy_hash))
-                .await
-                .map_err(|e| {
-                    ::capnp::Error::failed(format!("Failed to dispatch notification: {}", e))
-                })
+            let notification = query_tip(&clients).await;
+            deliver(handler, notification).await
         };
 
         ::capnp::capability::Promise::from_future(future)
@@ -252,19 +593,10 @@ impl chain_notifications::Server for ChainNotificationHandler {
         _params: chain_notifications::ChainStateFlushedParams,
         _: chain_notifications::ChainStateFlushedResults,
     ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
-        let handler = self.clone();
-
-        let future = async move {
-            // Dispatch notification
-            handler
-                .dispatch_notification(ChainNotification::ChainStateFlushed)
-                .await
-                .map_err(|e| {
-                    ::capnp::Error::failed(format!("Failed to dispatch notification: {}", e))
-                })
-        };
-
-        ::capnp::capability::Promise::from_future(future)
+        ::capnp::capability::Promise::from_future(deliver(
+            self.handler.clone(),
+            Ok(ChainNotification::ChainStateFlushed),
+        ))
     }
 
     fn destroy(
@@ -275,3 +607,157 @@ impl chain_notifications::Server for ChainNotificationHandler {
         ::capnp::capability::Promise::ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mempool_removal_reason_from_code() {
+        assert_eq!(MempoolRemovalReason::from(0), MempoolRemovalReason::Expiry);
+        assert_eq!(MempoolRemovalReason::from(3), MempoolRemovalReason::Block);
+        assert_eq!(
+            MempoolRemovalReason::from(5),
+            MempoolRemovalReason::Replaced
+        );
+        assert_eq!(
+            MempoolRemovalReason::from(6),
+            MempoolRemovalReason::Unknown(6)
+        );
+        assert_eq!(
+            MempoolRemovalReason::from(-1),
+            MempoolRemovalReason::Unknown(-1)
+        );
+    }
+
+    struct Counter(Arc<AtomicU64>);
+
+    #[async_trait]
+    impl NotificationHandler for Counter {
+        async fn handle_notification(&self, _: ChainNotification) -> Result<(), BlockTalkError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    struct Failing;
+
+    #[async_trait]
+    impl NotificationHandler for Failing {
+        async fn handle_notification(&self, _: ChainNotification) -> Result<(), BlockTalkError> {
+            Err(BlockTalkError::Connection("handler failed".to_string()))
+        }
+    }
+
+    struct Panicking;
+
+    #[async_trait]
+    impl NotificationHandler for Panicking {
+        async fn handle_notification(&self, _: ChainNotification) -> Result<(), BlockTalkError> {
+            panic!("handler panicked");
+        }
+    }
+
+    struct Slow;
+
+    #[async_trait]
+    impl NotificationHandler for Slow {
+        async fn handle_notification(&self, _: ChainNotification) -> Result<(), BlockTalkError> {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_isolates_handlers() {
+        for mode in [DispatchMode::Sequential, DispatchMode::Concurrent] {
+            let mut dispatcher = ChainNotificationHandler::new();
+            let count = Arc::new(AtomicU64::new(0));
+            let failures = Arc::new(Mutex::new(Vec::new()));
+            let reported = failures.clone();
+            dispatcher
+                .set_dispatch_options(DispatchOptions {
+                    mode,
+                    handler_timeout: Some(Duration::from_millis(20)),
+                    on_error: Some(Arc::new(move |id, failure| {
+                        reported.lock().unwrap().push((id, failure.to_string()));
+                    })),
+                })
+                .unwrap();
+
+            let failing = dispatcher
+                .register_handler(Arc::new(Failing))
+                .await
+                .unwrap();
+            let panicking = dispatcher
+                .register_handler(Arc::new(Panicking))
+                .await
+                .unwrap();
+            let slow = dispatcher.register_handler(Arc::new(Slow)).await.unwrap();
+            let counter = dispatcher
+                .register_handler(Arc::new(Counter(count.clone())))
+                .await
+                .unwrap();
+
+            assert!(dispatcher
+                .dispatch_notification(ChainNotification::ChainStateFlushed)
+                .await
+                .is_ok());
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+
+            let failures = failures.lock().unwrap();
+            assert_eq!(failures.len(), 3);
+            assert!(failures.iter().any(|(id, _)| *id == failing));
+            assert!(failures.iter().any(|(id, _)| *id == panicking));
+            assert!(failures.iter().any(|(id, _)| *id == slow));
+
+            let metrics = dispatcher.metrics().unwrap();
+            let metrics_of = |id| metrics.iter().find(|(m, _)| *m == id).unwrap().1.clone();
+            assert_eq!(metrics_of(failing).errors, 1);
+            assert_eq!(metrics_of(panicking).panics, 1);
+            assert_eq!(metrics_of(slow).timeouts, 1);
+            assert_eq!(metrics_of(counter).calls, 1);
+        }
+    }
+    #[tokio::test]
+    async fn test_record_missed() {
+        let mut dispatcher = ChainNotificationHandler::new();
+        let count = Arc::new(AtomicU64::new(0));
+        let id = dispatcher
+            .register_handler(Arc::new(Counter(count.clone())))
+            .await
+            .unwrap();
+
+        dispatcher.record_missed();
+
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        let metrics = dispatcher.metrics().unwrap();
+        assert_eq!(metrics[0].0, id);
+        assert_eq!(metrics[0].1.missed, 1);
+        assert_eq!(metrics[0].1.calls, 0);
+    }
+
+    #[tokio::test]
+    async fn test_deliver_never_fails() {
+        let mut dispatcher = ChainNotificationHandler::new();
+        let count = Arc::new(AtomicU64::new(0));
+        dispatcher
+            .register_handler(Arc::new(Counter(count.clone())))
+            .await
+            .unwrap();
+
+        let undecodable =
+            decode_transaction(&[0x01, 0x02]).map(ChainNotification::TransactionAddedToMempool);
+        assert!(deliver(dispatcher.clone(), undecodable).await.is_ok());
+        assert!(
+            deliver(dispatcher.clone(), Ok(ChainNotification::ChainStateFlushed))
+                .await
+                .is_ok()
+        );
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        let metrics = dispatcher.metrics().unwrap();
+        assert_eq!(metrics[0].1.missed, 1);
+        assert_eq!(metrics[0].1.calls, 1);
+    }
+}
@@ -0,0 +1,165 @@
+use bitcoin::{Block, BlockHash};
+
+use crate::blocks::{bytes_to_block_hash, decode_block};
+use crate::chain_capnp::{found_block_param, found_block_result};
+use crate::BlockTalkError;
+
+/// Fields to look up for a block, mirroring Core's `FoundBlock`.
+///
+/// Only requested fields are filled in by the node and returned in
+/// `BlockMeta`, so metadata lookups don't transfer the block itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockQuery {
+    pub hash: bool,
+    pub height: bool,
+    pub time: bool,
+    pub max_time: bool,
+    /// Median time past
+    pub mtp_time: bool,
+    pub in_active_chain: bool,
+    /// Full block data
+    pub data: bool,
+    /// Fields to look up for the next block in the active chain
+    pub next_block: Option<Box<BlockQuery>>,
+}
+
+impl BlockQuery {
+    /// Every field except the block data
+    pub fn metadata() -> Self {
+        Self {
+            hash: true,
+            height: true,
+            time: true,
+            max_time: true,
+            mtp_time: true,
+            in_active_chain: true,
+            data: false,
+            next_block: None,
+        }
+    }
+
+    /// Also look up `next` for the block's successor in the active chain
+    pub fn with_next_block(mut self, next: BlockQuery) -> Self {
+        self.next_block = Some(Box::new(next));
+        self
+    }
+
+    pub(crate) fn encode(&self, mut param: found_block_param::Builder) {
+        param.set_want_hash(self.hash);
+        param.set_want_height(self.height);
+        param.set_want_time(self.time);
+        param.set_want_max_time(self.max_time);
+        param.set_want_mtp_time(self.mtp_time);
+        param.set_want_in_active_chain(self.in_active_chain);
+        param.set_want_data(self.data);
+        if let Some(next) = &self.next_block {
+            next.encode(param.init_next_block());
+        }
+    }
+}
+
+/// Block fields returned for a `BlockQuery`. Fields that were not
+/// requested are `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockMeta {
+    pub hash: Option<BlockHash>,
+    pub height: Option<i32>,
+    pub time: Option<i64>,
+    pub max_time: Option<i64>,
+    pub mtp_time: Option<i64>,
+    pub in_active_chain: Option<bool>,
+    /// `None` if not requested or if the node has no data for the block,
+    /// e.g. because it was pruned
+    pub block: Option<Block>,
+    /// Successor in the active chain, `None` if not requested or if this
+    /// block is the tip or not in the active chain
+    pub next_block: Option<Box<BlockMeta>>,
+}
+
+impl BlockMeta {
+    pub(crate) fn decode(
+        query: &BlockQuery,
+        result: found_block_result::Reader,
+    ) -> Result<Self, BlockTalkError> {
+        let block = if query.data {
+            decode_block(result.get_data()?)?
+        } else {
+            None
+        };
+
+        let next_block = match &query.next_block {
+            Some(next) if result.has_next_block() => {
+                let next_result = result.get_next_block()?;
+                if next_result.get_found() {
+                    Some(Box::new(Self::decode(next, next_result)?))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            hash: query
+                .hash
+                .then(|| bytes_to_block_hash(result.get_hash()?))
+                .transpose()?,
+            height: query.height.then(|| result.get_height()),
+            time: query.time.then(|| result.get_time()),
+            max_time: query.max_time.then(|| result.get_max_time()),
+            mtp_time: query.mtp_time.then(|| result.get_mtp_time()),
+            in_active_chain: query
+                .in_active_chain
+                .then(|| result.get_in_active_chain() != 0),
+            block,
+            next_block,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    #[test]
+    fn test_decode_only_requested_fields() {
+        let hash = BlockHash::from_byte_array([7; 32]);
+        let next_hash = BlockHash::from_byte_array([8; 32]);
+
+        let mut message = capnp::message::Builder::new_default();
+        let mut result = message.init_root::<found_block_result::Builder>();
+        result.set_hash(hash.as_ref());
+        result.set_height(100);
+        result.set_time(1_700_000_000);
+        result.set_in_active_chain(1);
+        result.set_found(true);
+        {
+            let mut next = result.reborrow().init_next_block();
+            next.set_hash(next_hash.as_ref());
+            next.set_height(101);
+            next.set_found(true);
+        }
+
+        let query = BlockQuery {
+            hash: true,
+            in_active_chain: true,
+            ..Default::default()
+        }
+        .with_next_block(BlockQuery {
+            hash: true,
+            ..Default::default()
+        });
+        let meta = BlockMeta::decode(&query, result.into_reader()).unwrap();
+
+        assert_eq!(meta.hash, Some(hash));
+        assert_eq!(meta.in_active_chain, Some(true));
+        assert_eq!(meta.height, None);
+        assert_eq!(meta.time, None);
+        assert!(meta.block.is_none());
+
+        let next = meta.next_block.unwrap();
+        assert_eq!(next.hash, Some(next_hash));
+        assert_eq!(next.height, None);
+    }
+}
@@ -0,0 +1,438 @@
+use capnp::capability::Promise;
+use capnp_rpc::pry;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::chain_capnp::{actor_callback, j_s_o_n_r_p_c_request};
+use crate::connection::with_thread;
+use crate::error::ChainErrorKind;
+use crate::handler_capnp::handler::Client as HandlerClient;
+use crate::settings::{parse_value, text_to_string};
+use crate::{BlockTalkError, Connection, RpcClients};
+
+/// Identifies the commands registered by this process, so the node's RPC
+/// table can tell them apart from other clients' commands of the same name
+static NEXT_COMMAND_ID: AtomicI64 = AtomicI64::new(1);
+
+/// What the node's JSON-RPC server wants from a handler, mirroring
+/// `JSONRPCRequest::Mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcRequestMode {
+    Execute,
+    /// Answered with `RpcCommand::help` without calling the handler
+    GetHelp,
+    /// Answered with the argument map of `RpcCommand::args` without calling
+    /// the handler
+    GetArgs,
+}
+
+impl TryFrom<u32> for RpcRequestMode {
+    type Error = BlockTalkError;
+
+    fn try_from(mode: u32) -> Result<Self, Self::Error> {
+        match mode {
+            0 => Ok(Self::Execute),
+            1 => Ok(Self::GetHelp),
+            2 => Ok(Self::GetArgs),
+            _ => Err(BlockTalkError::chain_error(
+                ChainErrorKind::DeserializationFailed,
+                format!("Unknown RPC request mode: {}", mode),
+            )),
+        }
+    }
+}
+
+/// JSON-RPC request received by the node, passed to an `RpcHandler`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcRequest {
+    pub id: Value,
+    pub method: String,
+    /// Parameters as an array. The node maps named parameters to positions
+    /// with `RpcCommand::args`
+    pub params: Value,
+    pub mode: RpcRequestMode,
+    /// Request path, e.g. `/wallet/<name>`
+    pub uri: String,
+    pub auth_user: String,
+    pub peer_addr: String,
+    pub version: i32,
+}
+
+impl RpcRequest {
+    fn decode(request: j_s_o_n_r_p_c_request::Reader) -> Result<Self, BlockTalkError> {
+        Ok(Self {
+            id: parse_value(&text_to_string(request.get_id()?)?)?,
+            method: text_to_string(request.get_method()?)?,
+            params: parse_value(&text_to_string(request.get_params()?)?)?,
+            mode: RpcRequestMode::try_from(request.get_mode())?,
+            uri: text_to_string(request.get_uri()?)?,
+            auth_user: text_to_string(request.get_auth_user()?)?,
+            peer_addr: text_to_string(request.get_peer_addr()?)?,
+            version: request.get_version(),
+        })
+    }
+}
+
+/// JSON-RPC error returned to the caller, with one of Core's `RPCErrorCode`s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl RpcError {
+    pub const MISC_ERROR: i32 = -1;
+    pub const TYPE_ERROR: i32 = -3;
+    pub const INVALID_ADDRESS_OR_KEY: i32 = -5;
+    pub const INVALID_PARAMETER: i32 = -8;
+    pub const INVALID_PARAMS: i32 = -32602;
+
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_parameter(message: impl Into<String>) -> Self {
+        Self::new(Self::INVALID_PARAMETER, message)
+    }
+
+    /// Error object as built by Core's `JSONRPCError`
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "code": self.code,
+            "message": self.message,
+        })
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RPC error {}: {}", self.code, self.message)
+    }
+}
+
+impl Error for RpcError {}
+
+/// Argument of an `RpcCommand`, used to map named parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcArg {
+    pub name: String,
+    /// Only accepted as a named parameter
+    pub named_only: bool,
+}
+
+impl RpcArg {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            named_only: false,
+        }
+    }
+
+    pub fn named_only(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            named_only: true,
+        }
+    }
+}
+
+/// RPC method to register on the node, mirroring Core's `CRPCCommand`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcCommand {
+    /// Category the method is listed under by `help`
+    pub category: String,
+    pub name: String,
+    pub args: Vec<RpcArg>,
+    /// Text returned for `help <name>`
+    pub help: String,
+}
+
+impl RpcCommand {
+    pub fn new(category: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            category: category.into(),
+            name: name.into(),
+            args: Vec::new(),
+            help: String::new(),
+        }
+    }
+
+    pub fn with_arg(mut self, arg: RpcArg) -> Self {
+        self.args.push(arg);
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = help.into();
+        self
+    }
+
+    /// Argument map as built by Core's `RPCHelpMan::GetArgMap`: one
+    /// `[method, position, name, is_string]` entry per argument. Argument
+    /// types are unknown here, so values are never forced to strings
+    fn arg_map(&self) -> Value {
+        Value::Array(
+            self.args
+                .iter()
+                .enumerate()
+                .map(|(i, arg)| serde_json::json!([self.name, i, arg.name, false]))
+                .collect(),
+        )
+    }
+}
+
+/// Handles the requests of a registered RPC method.
+#[async_trait::async_trait]
+pub trait RpcHandler: Send + Sync {
+    /// Handle `request`, returning the JSON result or an error for the caller
+    async fn handle(&self, request: RpcRequest) -> Result<Value, RpcError>;
+}
+
+#[async_trait::async_trait]
+pub trait RpcInterface: Send + Sync {
+    /// Register `command` with the node's JSON-RPC server through
+    /// `handleRpc`. The method stays available until the returned
+    /// registration is dropped and is restored after a reconnect
+    async fn register_rpc(
+        &self,
+        command: RpcCommand,
+        handler: Arc<dyn RpcHandler>,
+    ) -> Result<RpcRegistration, BlockTalkError>;
+}
+
+pub struct NodeRpc {
+    connection: Arc<Connection>,
+}
+
+#[async_trait::async_trait]
+impl RpcInterface for NodeRpc {
+    async fn register_rpc(
+        &self,
+        command: RpcCommand,
+        handler: Arc<dyn RpcHandler>,
+    ) -> Result<RpcRegistration, BlockTalkError> {
+        log::debug!("Registering RPC method {}", command.name);
+        let command = Arc::new(command);
+        let unique_id = NEXT_COMMAND_ID.fetch_add(1, Ordering::Relaxed);
+
+        let id = self
+            .connection
+            .run({
+                let command = command.clone();
+                let handler = handler.clone();
+                move |clients| handle_rpc(clients, command, unique_id, handler)
+            })
+            .await?;
+        let node_handler = Arc::new(Mutex::new(id));
+
+        // Register again whenever a supervised connection restores its session.
+        let restored = node_handler.clone();
+        let hook = self.connection.add_session_hook({
+            let command = command.clone();
+            move |clients| {
+                let command = command.clone();
+                let handler = handler.clone();
+                let restored = restored.clone();
+                async move {
+                    let id = handle_rpc(clients, command, unique_id, handler).await?;
+                    *restored.lock().unwrap_or_else(|e| e.into_inner()) = id;
+                    Ok(())
+                }
+            }
+        });
+
+        log::info!("Registered RPC method {}", command.name);
+        Ok(RpcRegistration {
+            connection: self.connection.clone(),
+            name: command.name.clone(),
+            hook,
+            node_handler,
+        })
+    }
+}
+
+impl NodeRpc {
+    pub fn new(connection: Arc<Connection>) -> Self {
+        Self { connection }
+    }
+}
+
+/// A method registered with `RpcInterface::register_rpc`. Dropping it
+/// removes the method from the node.
+pub struct RpcRegistration {
+    connection: Arc<Connection>,
+    name: String,
+    /// Session hook re-registering after a reconnect
+    hook: u64,
+    /// Capability id of the node's `Handler`, updated when the hook runs
+    node_handler: Arc<Mutex<u64>>,
+}
+
+impl RpcRegistration {
+    /// Name of the registered method
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for RpcRegistration {
+    fn drop(&mut self) {
+        self.connection.remove_session_hook(self.hook);
+        let id = *self.node_handler.lock().unwrap_or_else(|e| e.into_inner());
+        let name = std::mem::take(&mut self.name);
+        self.connection.run_detached(move |clients| async move {
+            let Some(handler) = clients.capabilities.remove::<HandlerClient>(id) else {
+                return Ok(());
+            };
+            let req = with_thread!(handler.disconnect_request(), clients.thread);
+            req.send().promise.await?;
+
+            let req = with_thread!(handler.destroy_request(), clients.thread);
+            req.send().promise.await?;
+            log::debug!("Unregistered RPC method {}", name);
+            Ok(())
+        });
+    }
+}
+
+/// Register `command` with the node's `handleRpc` on the given session.
+/// Returns the capability id of the node's `Handler`.
+async fn handle_rpc(
+    clients: RpcClients,
+    command: Arc<RpcCommand>,
+    unique_id: i64,
+    handler: Arc<dyn RpcHandler>,
+) -> Result<u64, BlockTalkError> {
+    let actor: actor_callback::Client = capnp_rpc::new_client(ActorServer {
+        command: command.clone(),
+        handler,
+    });
+    let mut req = with_thread!(clients.chain.handle_rpc_request(), clients.thread);
+
+    let mut params = req.get().init_command();
+    params.set_category(command.category.as_str());
+    params.set_name(command.name.as_str());
+    params.set_actor(actor);
+    params.set_unique_id(unique_id);
+    let mut arg_names = params.init_arg_names(command.args.len() as u32);
+    for (i, arg) in command.args.iter().enumerate() {
+        let mut arg_name = arg_names.reborrow().get(i as u32);
+        arg_name.set_name(arg.name.as_str());
+        arg_name.set_named_only(arg.named_only);
+    }
+
+    let response = req.send().promise.await.map_err(|e| {
+        log::error!("Failed to register RPC method {}: {}", command.name, e);
+        BlockTalkError::Connection(e.to_string())
+    })?;
+    let node_handler: HandlerClient = response.get()?.get_result()?;
+    Ok(clients.capabilities.insert(node_handler))
+}
+
+/// `ActorCallback` server the node calls for every request to a method.
+struct ActorServer {
+    command: Arc<RpcCommand>,
+    handler: Arc<dyn RpcHandler>,
+}
+
+impl actor_callback::Server for ActorServer {
+    fn call(
+        &mut self,
+        params: actor_callback::CallParams,
+        mut results: actor_callback::CallResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let request = pry!(RpcRequest::decode(pry!(pry!(params.get()).get_request()))
+            .map_err(|e| ::capnp::Error::failed(format!("Failed to decode RPC request: {}", e))));
+
+        match request.mode {
+            // Core's `help` reads the help text from the exception message
+            RpcRequestMode::GetHelp => {
+                results.get().set_error(self.command.help.as_str());
+                return Promise::ok(());
+            }
+            RpcRequestMode::GetArgs => {
+                let mut results = results.get();
+                results.set_response(self.command.arg_map().to_string().as_str());
+                results.set_result(true);
+                return Promise::ok(());
+            }
+            RpcRequestMode::Execute => {}
+        }
+
+        let handler = self.handler.clone();
+        Promise::from_future(async move {
+            let method = request.method.clone();
+            let result = handler.handle(request).await;
+            let mut results = results.get();
+            match result {
+                Ok(value) => {
+                    results.set_response(value.to_string().as_str());
+                    results.set_result(true);
+                }
+                Err(e) => {
+                    log::debug!("RPC method {} failed: {}", method, e);
+                    results.set_rpc_error(e.to_json().to_string().as_str());
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_decode_request() {
+        let mut message = capnp::message::Builder::new_default();
+        let mut request = message.init_root::<j_s_o_n_r_p_c_request::Builder>();
+        request.set_id("7");
+        request.set_method("getgreeting");
+        request.set_params(r#"["satoshi"]"#);
+        request.set_mode(0);
+        request.set_uri("/");
+        request.set_version(2);
+
+        let request = RpcRequest::decode(request.into_reader()).unwrap();
+        assert_eq!(request.id, json!(7));
+        assert_eq!(request.method, "getgreeting");
+        assert_eq!(request.params, json!(["satoshi"]));
+        assert_eq!(request.mode, RpcRequestMode::Execute);
+        assert_eq!(request.auth_user, "");
+        assert_eq!(request.version, 2);
+
+        assert!(RpcRequestMode::try_from(3).is_err());
+    }
+
+    #[test]
+    fn test_arg_map() {
+        let command = RpcCommand::new("greeting", "getgreeting")
+            .with_arg(RpcArg::new("name"))
+            .with_arg(RpcArg::named_only("formal"));
+        assert_eq!(
+            command.arg_map(),
+            json!([
+                ["getgreeting", 0, "name", false],
+                ["getgreeting", 1, "formal", false],
+            ])
+        );
+        assert_eq!(RpcCommand::new("greeting", "ping").arg_map(), json!([]));
+    }
+
+    #[test]
+    fn test_rpc_error_json() {
+        let error = RpcError::invalid_parameter("Invalid name");
+        assert_eq!(
+            error.to_json(),
+            json!({"code": -8, "message": "Invalid name"})
+        );
+    }
+}
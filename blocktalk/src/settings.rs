@@ -0,0 +1,364 @@
+use capnp::capability::Promise;
+use capnp_rpc::pry;
+use serde_json::Value;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use crate::chain_capnp::settings_update_callback;
+use crate::connection::with_thread;
+use crate::error::ChainErrorKind;
+use crate::notification::panic_message;
+use crate::{BlockTalkError, Connection};
+
+/// Whether a read/write settings change is saved to `settings.json`,
+/// mirroring Core's `interfaces::SettingsAction`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SettingsAction {
+    /// Update the setting and write the settings file
+    #[default]
+    Write,
+    /// Update the setting in memory only
+    SkipWrite,
+}
+
+impl SettingsAction {
+    fn code(self) -> i32 {
+        match self {
+            SettingsAction::Write => 0,
+            SettingsAction::SkipWrite => 1,
+        }
+    }
+}
+
+/// Edits a read/write setting in place. The node calls it once with the
+/// current value (`Null` if unset) while holding its settings lock. Returning
+/// `None` discards the change.
+pub type SettingsUpdate = Box<dyn FnOnce(&mut Value) -> Option<SettingsAction> + Send>;
+
+#[async_trait::async_trait]
+pub trait SettingsInterface: Send + Sync {
+    /// Value of a setting from the command line, config file or read/write
+    /// settings, `Null` if unset
+    async fn get_setting(&self, name: &str) -> Result<Value, BlockTalkError>;
+
+    /// Every value given for a setting, e.g. repeated `-wallet` options
+    async fn get_settings_list(&self, name: &str) -> Result<Vec<Value>, BlockTalkError>;
+
+    /// Value of a read/write setting stored in `settings.json`, `Null` if unset
+    async fn get_rw_setting(&self, name: &str) -> Result<Value, BlockTalkError>;
+
+    /// Atomically read and modify a read/write setting with `update`
+    /// Returns `false` if the settings file could not be written
+    async fn update_rw_setting(
+        &self,
+        name: &str,
+        update: SettingsUpdate,
+    ) -> Result<bool, BlockTalkError>;
+
+    /// Replace a read/write setting. A `Null` value removes it
+    async fn overwrite_rw_setting(
+        &self,
+        name: &str,
+        value: Value,
+        action: SettingsAction,
+    ) -> Result<bool, BlockTalkError>;
+
+    /// Remove a read/write setting
+    async fn delete_rw_settings(
+        &self,
+        name: &str,
+        action: SettingsAction,
+    ) -> Result<bool, BlockTalkError>;
+}
+
+pub struct NodeSettings {
+    connection: Arc<Connection>,
+}
+
+#[async_trait::async_trait]
+impl SettingsInterface for NodeSettings {
+    async fn get_setting(&self, name: &str) -> Result<Value, BlockTalkError> {
+        log::debug!("Getting setting {}", name);
+        let name = name.to_string();
+        let value = self
+            .connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(clients.chain.get_setting_request(), clients.thread);
+
+                req.get().set_name(name.as_str());
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get setting {}: {}", name, e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                text_to_string(response.get()?.get_result()?)
+            })
+            .await?;
+
+        parse_value(&value)
+    }
+
+    async fn get_settings_list(&self, name: &str) -> Result<Vec<Value>, BlockTalkError> {
+        log::debug!("Getting settings list {}", name);
+        let name = name.to_string();
+        let values = self
+            .connection
+            .run(move |clients| async move {
+                let mut req =
+                    with_thread!(clients.chain.get_settings_list_request(), clients.thread);
+
+                req.get().set_name(name.as_str());
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get settings list {}: {}", name, e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                response
+                    .get()?
+                    .get_result()?
+                    .iter()
+                    .map(|value| text_to_string(value?))
+                    .collect::<Result<Vec<_>, BlockTalkError>>()
+            })
+            .await?;
+
+        values.iter().map(|value| parse_value(value)).collect()
+    }
+
+    async fn get_rw_setting(&self, name: &str) -> Result<Value, BlockTalkError> {
+        log::debug!("Getting read/write setting {}", name);
+        let name = name.to_string();
+        let value = self
+            .connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(clients.chain.get_rw_setting_request(), clients.thread);
+
+                req.get().set_name(name.as_str());
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get read/write setting {}: {}", name, e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                text_to_string(response.get()?.get_result()?)
+            })
+            .await?;
+
+        parse_value(&value)
+    }
+
+    async fn update_rw_setting(
+        &self,
+        name: &str,
+        update: SettingsUpdate,
+    ) -> Result<bool, BlockTalkError> {
+        log::debug!("Updating read/write setting {}", name);
+        let name = name.to_string();
+        self.connection
+            .run(move |clients| async move {
+                let callback: settings_update_callback::Client =
+                    capnp_rpc::new_client(SettingsUpdateServer {
+                        update: Some(update),
+                    });
+                let mut req =
+                    with_thread!(clients.chain.update_rw_setting_request(), clients.thread);
+
+                let mut params = req.get();
+                params.set_name(name.as_str());
+                params.set_update(callback);
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to update read/write setting {}: {}", name, e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }
+
+    async fn overwrite_rw_setting(
+        &self,
+        name: &str,
+        value: Value,
+        action: SettingsAction,
+    ) -> Result<bool, BlockTalkError> {
+        log::debug!("Overwriting read/write setting {} ({:?})", name, action);
+        let name = name.to_string();
+        let value = value.to_string();
+        self.connection
+            .run(move |clients| async move {
+                let mut req =
+                    with_thread!(clients.chain.overwrite_rw_setting_request(), clients.thread);
+
+                let mut params = req.get();
+                params.set_name(name.as_str());
+                params.set_value(value.as_str());
+                params.set_action(action.code());
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to overwrite read/write setting {}: {}", name, e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }
+
+    async fn delete_rw_settings(
+        &self,
+        name: &str,
+        action: SettingsAction,
+    ) -> Result<bool, BlockTalkError> {
+        log::debug!("Deleting read/write setting {} ({:?})", name, action);
+        let name = name.to_string();
+        self.connection
+            .run(move |clients| async move {
+                let mut req =
+                    with_thread!(clients.chain.delete_rw_settings_request(), clients.thread);
+
+                let mut params = req.get();
+                params.set_name(name.as_str());
+                params.set_action(action.code());
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to delete read/write setting {}: {}", name, e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }
+}
+
+impl NodeSettings {
+    pub fn new(connection: Arc<Connection>) -> Self {
+        Self { connection }
+    }
+}
+
+/// `SettingsUpdateCallback` server running a `SettingsUpdate` for the node.
+struct SettingsUpdateServer {
+    update: Option<SettingsUpdate>,
+}
+
+impl settings_update_callback::Server for SettingsUpdateServer {
+    fn call(
+        &mut self,
+        params: settings_update_callback::CallParams,
+        mut results: settings_update_callback::CallResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let Some(update) = self.update.take() else {
+            return Promise::err(::capnp::Error::failed(
+                "Settings update already applied".to_string(),
+            ));
+        };
+        let value = pry!(pry!(params.get()).get_value());
+        let value = pry!(value
+            .to_str()
+            .map_err(|e| ::capnp::Error::failed(e.to_string())));
+
+        let (value, action) = pry!(apply_update(update, value)
+            .map_err(|e| ::capnp::Error::failed(format!("Failed to update setting: {}", e))));
+
+        let mut results = results.get();
+        results.set_value(value.as_str());
+        if let Some(action) = action {
+            results.set_result(action.code());
+            results.set_has_result(true);
+        }
+        Promise::ok(())
+    }
+
+    fn destroy(
+        &mut self,
+        _params: settings_update_callback::DestroyParams,
+        _: settings_update_callback::DestroyResults,
+    ) -> Promise<(), ::capnp::Error> {
+        Promise::ok(())
+    }
+}
+
+/// Run `update` on a JSON encoded setting and encode the result. A panic in
+/// `update` is returned as an error so it never unwinds into the RPC thread
+fn apply_update(
+    update: SettingsUpdate,
+    value: &str,
+) -> Result<(String, Option<SettingsAction>), BlockTalkError> {
+    let mut value = parse_value(value)?;
+    let action =
+        std::panic::catch_unwind(AssertUnwindSafe(|| update(&mut value))).map_err(|panic| {
+            let message = panic_message(panic.as_ref());
+            log::error!("Settings update panicked: {}", message);
+            BlockTalkError::chain_error(
+                ChainErrorKind::Other("Settings update panicked".to_string()),
+                message,
+            )
+        })?;
+    Ok((value.to_string(), action))
+}
+
+/// Parse a JSON encoded `SettingsValue`. Unset values may arrive empty
+pub(crate) fn parse_value(value: &str) -> Result<Value, BlockTalkError> {
+    if value.is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_str(value).map_err(|e| {
+        log::error!("Failed to decode setting value: {}", e);
+        BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+    })
+}
+
+pub(crate) fn text_to_string(text: capnp::text::Reader) -> Result<String, BlockTalkError> {
+    text.to_str().map(str::to_string).map_err(|e| {
+        log::error!("Setting value is not valid UTF-8: {}", e);
+        BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("").unwrap(), Value::Null);
+        assert_eq!(parse_value("null").unwrap(), Value::Null);
+        assert_eq!(
+            parse_value(r#"["a.dat","b"]"#).unwrap(),
+            json!(["a.dat", "b"])
+        );
+        assert!(parse_value("{").is_err());
+    }
+
+    #[test]
+    fn test_apply_update() {
+        let update: SettingsUpdate = Box::new(|value| {
+            if value.is_null() {
+                *value = json!([]);
+            }
+            value.as_array_mut()?.push(json!("wallet"));
+            Some(SettingsAction::Write)
+        });
+        let (value, action) = apply_update(update, "null").unwrap();
+        assert_eq!(value, r#"["wallet"]"#);
+        assert_eq!(action, Some(SettingsAction::Write));
+
+        let discard: SettingsUpdate = Box::new(|_| None);
+        let (value, action) = apply_update(discard, r#"{"a":1}"#).unwrap();
+        assert_eq!(value, r#"{"a":1}"#);
+        assert_eq!(action, None);
+    }
+
+    #[test]
+    fn test_apply_update_panic() {
+        let update: SettingsUpdate = Box::new(|_| panic!("bad update"));
+        let error = apply_update(update, "null").unwrap_err();
+        assert!(error.to_string().contains("bad update"));
+    }
+}
@@ -0,0 +1,125 @@
+use bitcoin::consensus::Decodable;
+use bitcoin::Transaction;
+use capnp::capability::Promise;
+use capnp_rpc::pry;
+use futures::future::BoxFuture;
+use futures::{FutureExt, Stream};
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+use crate::chain_capnp::chain_notifications;
+use crate::connection::with_thread;
+use crate::error::ChainErrorKind;
+use crate::{BlockTalkError, Connection};
+
+type Item = Result<Transaction, BlockTalkError>;
+
+/// Every transaction in the node's mempool, replayed by
+/// `requestMempoolTransactions`.
+///
+/// Created by `MempoolInterface::snapshot_stream`. The stream ends once the
+/// node has sent the whole mempool; if the replay fails the last item is the
+/// error. Transactions are buffered as they arrive so the node's replay never
+/// waits on the consumer.
+pub struct MempoolSnapshot {
+    receiver: mpsc::UnboundedReceiver<Item>,
+    /// Outcome of the `requestMempoolTransactions` call, `None` once known
+    replay: Option<BoxFuture<'static, Result<(), BlockTalkError>>>,
+    error: Option<BlockTalkError>,
+}
+
+impl MempoolSnapshot {
+    pub(crate) fn request(connection: &Connection) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let replay = connection
+            .run(move |clients| async move {
+                // The replay holds the node thread until the whole mempool
+                // has been sent, so it gets a thread of its own.
+                let thread = clients.make_thread().await?;
+                let slot = Rc::new(RefCell::new(Some(sender)));
+                let notifications: chain_notifications::Client =
+                    capnp_rpc::new_client(SnapshotServer {
+                        sender: slot.clone(),
+                    });
+                let mut req =
+                    with_thread!(clients.chain.request_mempool_transactions_request(), thread);
+
+                req.get().set_notifications(notifications);
+
+                let result = req.send().promise.await;
+                // Close the stream even if the node keeps the callback alive
+                slot.borrow_mut().take();
+                result.map_err(|e| {
+                    log::error!("Failed to request mempool transactions: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+                Ok(())
+            })
+            .boxed();
+
+        Self {
+            receiver,
+            replay: Some(replay),
+            error: None,
+        }
+    }
+}
+
+impl Stream for MempoolSnapshot {
+    type Item = Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(replay) = self.replay.as_mut() {
+            if let Poll::Ready(result) = replay.poll_unpin(cx) {
+                self.replay = None;
+                self.error = result.err();
+            }
+        }
+        // The sender is dropped when the replay ends, so buffered
+        // transactions are always returned before the error
+        match self.receiver.poll_recv(cx) {
+            Poll::Ready(None) if self.replay.is_none() => Poll::Ready(self.error.take().map(Err)),
+            Poll::Ready(None) => Poll::Pending,
+            poll => poll,
+        }
+    }
+}
+
+/// `ChainNotifications` callback receiving the replayed transactions.
+struct SnapshotServer {
+    sender: Rc<RefCell<Option<mpsc::UnboundedSender<Item>>>>,
+}
+
+impl chain_notifications::Server for SnapshotServer {
+    fn transaction_added_to_mempool(
+        &mut self,
+        params: chain_notifications::TransactionAddedToMempoolParams,
+        _: chain_notifications::TransactionAddedToMempoolResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let Some(sender) = self.sender.borrow().clone() else {
+            return Promise::ok(());
+        };
+
+        let tx =
+            Transaction::consensus_decode(&mut pry!(pry!(params.get()).get_tx())).map_err(|e| {
+                log::error!("Failed to decode mempool transaction: {}", e);
+                BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+            });
+
+        // A dropped stream is not an error for the node
+        let _ = sender.send(tx);
+        Promise::ok(())
+    }
+
+    fn destroy(
+        &mut self,
+        _params: chain_notifications::DestroyParams,
+        _: chain_notifications::DestroyResults,
+    ) -> Promise<(), ::capnp::Error> {
+        Promise::ok(())
+    }
+}
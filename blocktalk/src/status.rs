@@ -0,0 +1,321 @@
+use bitcoin::BlockHash;
+use std::sync::Arc;
+
+use crate::blocks::bytes_to_block_hash;
+use crate::connection::with_thread;
+use crate::{BlockTalkError, Connection};
+
+/// Snapshot of the node's sync, pruning and lifecycle state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeStatus {
+    /// Height and hash of the active tip, `None` before the genesis block
+    /// is loaded
+    pub tip: Option<(i32, BlockHash)>,
+    pub initial_block_download: bool,
+    /// Estimated fraction of all transactions verified at the tip, 0.0 to 1.0
+    pub verification_progress: f64,
+    /// Whether any block files have been pruned
+    pub pruned: bool,
+    /// Height of the highest pruned block, if any
+    pub prune_height: Option<i32>,
+    /// Whether the node has finished loading and will relay transactions
+    pub ready_to_broadcast: bool,
+    pub shutdown_requested: bool,
+    /// Whether the active chainstate is an unvalidated assumeutxo snapshot
+    pub assumed_valid_chain: bool,
+}
+
+#[async_trait::async_trait]
+pub trait NodeStatusInterface: Send + Sync {
+    /// Query every status field at once
+    async fn status(&self) -> Result<NodeStatus, BlockTalkError>;
+
+    /// Check if the node is in initial block download
+    async fn is_initial_block_download(&self) -> Result<bool, BlockTalkError>;
+
+    /// Estimated fraction of all transactions verified up to `block_hash`
+    async fn verification_progress(&self, block_hash: &BlockHash) -> Result<f64, BlockTalkError>;
+
+    /// Check if any block files have been pruned
+    async fn have_pruned(&self) -> Result<bool, BlockTalkError>;
+
+    /// Height of the highest pruned block, `None` if nothing was pruned
+    async fn prune_height(&self) -> Result<Option<i32>, BlockTalkError>;
+
+    /// Check if block data is on disk for every ancestor of `block_hash`
+    /// between `min_height` and `max_height` (or `block_hash` itself)
+    async fn has_blocks(
+        &self,
+        block_hash: &BlockHash,
+        min_height: i32,
+        max_height: Option<i32>,
+    ) -> Result<bool, BlockTalkError>;
+
+    /// Check if the active chain block at `height` has its data on disk
+    async fn have_block_on_disk(&self, height: i32) -> Result<bool, BlockTalkError>;
+
+    /// Check if the node has finished loading and will relay transactions
+    async fn is_ready_to_broadcast(&self) -> Result<bool, BlockTalkError>;
+
+    /// Check if the node is shutting down
+    async fn shutdown_requested(&self) -> Result<bool, BlockTalkError>;
+
+    /// Check if the active chainstate is an unvalidated assumeutxo snapshot
+    async fn has_assumed_valid_chain(&self) -> Result<bool, BlockTalkError>;
+}
+
+pub struct Node {
+    connection: Arc<Connection>,
+}
+
+/// Call a context-only `Chain` method returning a `Bool`.
+macro_rules! bool_call {
+    ($self:ident, $request:ident, $what:literal) => {{
+        log::debug!("Checking {}", $what);
+        $self
+            .connection
+            .run(|clients| async move {
+                let req = with_thread!(clients.chain.$request(), clients.thread);
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to check {}: {}", $what, e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }};
+}
+
+#[async_trait::async_trait]
+impl NodeStatusInterface for Node {
+    async fn status(&self) -> Result<NodeStatus, BlockTalkError> {
+        log::debug!("Fetching node status");
+        let (tip, progress, flags, prune_height) = self
+            .connection
+            .run(|clients| async move {
+                // Independent calls are sent together and answered in order
+                let ibd = with_thread!(
+                    clients.chain.is_initial_block_download_request(),
+                    clients.thread
+                )
+                .send();
+                let pruned =
+                    with_thread!(clients.chain.have_pruned_request(), clients.thread).send();
+                let prune_height =
+                    with_thread!(clients.chain.get_prune_height_request(), clients.thread).send();
+                let ready = with_thread!(
+                    clients.chain.is_ready_to_broadcast_request(),
+                    clients.thread
+                )
+                .send();
+                let shutdown =
+                    with_thread!(clients.chain.shutdown_requested_request(), clients.thread).send();
+                let assumed_valid = with_thread!(
+                    clients.chain.has_assumed_valid_chain_request(),
+                    clients.thread
+                )
+                .send();
+
+                let height = {
+                    let req = with_thread!(clients.chain.get_height_request(), clients.thread);
+                    let response = req.send().promise.await.map_err(|e| {
+                        log::error!("Failed to get tip height: {}", e);
+                        BlockTalkError::Connection(e.to_string())
+                    })?;
+                    let result = response.get()?;
+                    result.get_has_result().then(|| result.get_result())
+                };
+                let (tip, progress) = match height {
+                    Some(height) => {
+                        let mut hash_req =
+                            with_thread!(clients.chain.get_block_hash_request(), clients.thread);
+                        hash_req.get().set_height(height);
+                        let response = hash_req.send().promise.await.map_err(|e| {
+                            log::error!("Failed to get tip hash: {}", e);
+                            BlockTalkError::Connection(e.to_string())
+                        })?;
+                        let hash = response.get()?.get_result()?.to_vec();
+
+                        let mut progress_req = with_thread!(
+                            clients.chain.guess_verification_progress_request(),
+                            clients.thread
+                        );
+                        progress_req.get().set_block_hash(&hash);
+                        let response = progress_req.send().promise.await.map_err(|e| {
+                            log::error!("Failed to estimate verification progress: {}", e);
+                            BlockTalkError::Connection(e.to_string())
+                        })?;
+                        (Some((height, hash)), response.get()?.get_result())
+                    }
+                    None => (None, 0.0),
+                };
+
+                let (ibd, pruned, prune_height, ready, shutdown, assumed_valid) =
+                    futures::try_join!(
+                        ibd.promise,
+                        pruned.promise,
+                        prune_height.promise,
+                        ready.promise,
+                        shutdown.promise,
+                        assumed_valid.promise,
+                    )
+                    .map_err(|e| {
+                        log::error!("Failed to fetch node status: {}", e);
+                        BlockTalkError::Connection(e.to_string())
+                    })?;
+                let prune_height = prune_height.get()?;
+
+                Ok((
+                    tip,
+                    progress,
+                    [
+                        ibd.get()?.get_result(),
+                        pruned.get()?.get_result(),
+                        ready.get()?.get_result(),
+                        shutdown.get()?.get_result(),
+                        assumed_valid.get()?.get_result(),
+                    ],
+                    prune_height
+                        .get_has_result()
+                        .then(|| prune_height.get_result()),
+                ))
+            })
+            .await?;
+
+        let [initial_block_download, pruned, ready_to_broadcast, shutdown_requested, assumed_valid_chain] =
+            flags;
+        let tip = match tip {
+            Some((height, hash)) => Some((height, bytes_to_block_hash(&hash)?)),
+            None => None,
+        };
+        Ok(NodeStatus {
+            tip,
+            initial_block_download,
+            verification_progress: progress,
+            pruned,
+            prune_height,
+            ready_to_broadcast,
+            shutdown_requested,
+            assumed_valid_chain,
+        })
+    }
+
+    async fn is_initial_block_download(&self) -> Result<bool, BlockTalkError> {
+        bool_call!(
+            self,
+            is_initial_block_download_request,
+            "initial block download"
+        )
+    }
+
+    async fn verification_progress(&self, block_hash: &BlockHash) -> Result<f64, BlockTalkError> {
+        log::debug!("Estimating verification progress at {}", block_hash);
+        let block_hash = *block_hash;
+        self.connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(
+                    clients.chain.guess_verification_progress_request(),
+                    clients.thread
+                );
+                req.get().set_block_hash(block_hash.as_ref());
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to estimate verification progress: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }
+
+    async fn have_pruned(&self) -> Result<bool, BlockTalkError> {
+        bool_call!(self, have_pruned_request, "whether blocks were pruned")
+    }
+
+    async fn prune_height(&self) -> Result<Option<i32>, BlockTalkError> {
+        log::debug!("Fetching prune height");
+        self.connection
+            .run(|clients| async move {
+                let req = with_thread!(clients.chain.get_prune_height_request(), clients.thread);
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to get prune height: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+                let result = response.get()?;
+                Ok(result.get_has_result().then(|| result.get_result()))
+            })
+            .await
+    }
+
+    async fn has_blocks(
+        &self,
+        block_hash: &BlockHash,
+        min_height: i32,
+        max_height: Option<i32>,
+    ) -> Result<bool, BlockTalkError> {
+        log::debug!(
+            "Checking block data below {} from height {} to {:?}",
+            block_hash,
+            min_height,
+            max_height
+        );
+        let block_hash = *block_hash;
+        self.connection
+            .run(move |clients| async move {
+                let mut req = with_thread!(clients.chain.has_blocks_request(), clients.thread);
+                let mut params = req.get();
+                params.set_block_hash(block_hash.as_ref());
+                params.set_min_height(min_height);
+                params.set_max_height(max_height.unwrap_or_default());
+                params.set_has_max_height(max_height.is_some());
+
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to check block data: {}", e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }
+
+    async fn have_block_on_disk(&self, height: i32) -> Result<bool, BlockTalkError> {
+        log::debug!("Checking block data at height {}", height);
+        self.connection
+            .run(move |clients| async move {
+                let mut req =
+                    with_thread!(clients.chain.have_block_on_disk_request(), clients.thread);
+                req.get().set_height(height);
+                let response = req.send().promise.await.map_err(|e| {
+                    log::error!("Failed to check block data at height {}: {}", height, e);
+                    BlockTalkError::Connection(e.to_string())
+                })?;
+                Ok(response.get()?.get_result())
+            })
+            .await
+    }
+
+    async fn is_ready_to_broadcast(&self) -> Result<bool, BlockTalkError> {
+        bool_call!(
+            self,
+            is_ready_to_broadcast_request,
+            "readiness to broadcast"
+        )
+    }
+
+    async fn shutdown_requested(&self) -> Result<bool, BlockTalkError> {
+        bool_call!(self, shutdown_requested_request, "for shutdown request")
+    }
+
+    async fn has_assumed_valid_chain(&self) -> Result<bool, BlockTalkError> {
+        bool_call!(
+            self,
+            has_assumed_valid_chain_request,
+            "for assumed valid chain"
+        )
+    }
+}
+
+impl Node {
+    pub fn new(connection: Arc<Connection>) -> Self {
+        Self { connection }
+    }
+}
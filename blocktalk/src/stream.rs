@@ -0,0 +1,257 @@
+use async_trait::async_trait;
+use futures::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use tokio::sync::Notify;
+
+use crate::notification::{ChainNotificationHandler, HandlerId, NotificationHandler};
+use crate::{BlockTalkError, ChainNotification};
+
+/// What a notification stream does when its buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Hold the node callback until the consumer catches up. No events are
+    /// lost, but a slow consumer delays every other handler.
+    Block,
+    /// Silently drop the oldest buffered event.
+    DropOldest,
+    /// Drop the oldest buffered event and yield `StreamItem::Lagged`
+    /// with the number of dropped events before the next one.
+    Lagged,
+}
+
+/// Options for `ChainInterface::subscribe`.
+#[derive(Debug, Clone)]
+pub struct SubscribeOptions {
+    /// Number of events buffered before `lag_policy` applies
+    pub buffer: usize,
+    pub lag_policy: LagPolicy,
+}
+
+impl Default for SubscribeOptions {
+    fn default() -> Self {
+        Self {
+            buffer: 1024,
+            lag_policy: LagPolicy::Lagged,
+        }
+    }
+}
+
+/// Item of a `NotificationStream`.
+#[derive(Clone, Debug)]
+pub enum StreamItem {
+    Notification(ChainNotification),
+    /// The given number of notifications were dropped because the consumer
+    /// fell behind. Only yielded with `LagPolicy::Lagged`.
+    Lagged(u64),
+}
+
+struct Queue {
+    events: VecDeque<ChainNotification>,
+    lagged: u64,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    capacity: usize,
+    lag_policy: LagPolicy,
+    space: Notify,
+}
+
+impl Shared {
+    fn queue(&self) -> std::sync::MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Handler feeding a `NotificationStream`.
+struct StreamSink {
+    shared: Arc<Shared>,
+}
+
+#[async_trait]
+impl NotificationHandler for StreamSink {
+    async fn handle_notification(
+        &self,
+        notification: ChainNotification,
+    ) -> Result<(), BlockTalkError> {
+        loop {
+            let space = self.shared.space.notified();
+            {
+                let mut queue = self.shared.queue();
+                if queue.closed {
+                    return Ok(());
+                }
+                if queue.events.len() >= self.shared.capacity
+                    && self.shared.lag_policy != LagPolicy::Block
+                {
+                    queue.events.pop_front();
+                    if self.shared.lag_policy == LagPolicy::Lagged {
+                        queue.lagged += 1;
+                    }
+                }
+                if queue.events.len() < self.shared.capacity {
+                    queue.events.push_back(notification);
+                    if let Some(waker) = queue.waker.take() {
+                        waker.wake();
+                    }
+                    return Ok(());
+                }
+            }
+            space.await;
+        }
+    }
+}
+
+/// Chain notifications as a `futures::Stream`.
+///
+/// Created by `ChainInterface::subscribe`. The stream never ends on its own;
+/// dropping it unregisters its handler.
+pub struct NotificationStream {
+    shared: Arc<Shared>,
+    handlers: ChainNotificationHandler,
+    id: HandlerId,
+}
+
+impl NotificationStream {
+    pub(crate) async fn register(
+        mut handlers: ChainNotificationHandler,
+        options: SubscribeOptions,
+    ) -> Result<Self, BlockTalkError> {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                events: VecDeque::new(),
+                lagged: 0,
+                waker: None,
+                closed: false,
+            }),
+            capacity: options.buffer.max(1),
+            lag_policy: options.lag_policy,
+            space: Notify::new(),
+        });
+        let id = handlers
+            .register_handler(Arc::new(StreamSink {
+                shared: shared.clone(),
+            }))
+            .await?;
+        Ok(Self {
+            shared,
+            handlers,
+            id,
+        })
+    }
+}
+
+impl Stream for NotificationStream {
+    type Item = StreamItem;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut queue = self.shared.queue();
+        if queue.lagged > 0 {
+            let lagged = std::mem::take(&mut queue.lagged);
+            return Poll::Ready(Some(StreamItem::Lagged(lagged)));
+        }
+        match queue.events.pop_front() {
+            Some(notification) => {
+                self.shared.space.notify_one();
+                Poll::Ready(Some(StreamItem::Notification(notification)))
+            }
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for NotificationStream {
+    fn drop(&mut self) {
+        self.shared.queue().closed = true;
+        self.shared.space.notify_waiters();
+        if let Err(e) = self.handlers.remove_handler(self.id) {
+            log::error!("Failed to remove stream handler: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use bitcoin::BlockHash;
+    use futures::StreamExt;
+
+    fn tip(height: i32) -> ChainNotification {
+        ChainNotification::UpdatedBlockTip {
+            hash: BlockHash::all_zeros(),
+            height,
+            initial_block_download: false,
+        }
+    }
+
+    fn height(item: Option<StreamItem>) -> i32 {
+        match item {
+            Some(StreamItem::Notification(ChainNotification::UpdatedBlockTip {
+                height, ..
+            })) => height,
+            other => panic!("unexpected notification: {:?}", other),
+        }
+    }
+
+    async fn stream(lag_policy: LagPolicy) -> (NotificationStream, StreamSink) {
+        let options = SubscribeOptions {
+            buffer: 2,
+            lag_policy,
+        };
+        let stream = NotificationStream::register(ChainNotificationHandler::new(), options)
+            .await
+            .unwrap();
+        let sink = StreamSink {
+            shared: stream.shared.clone(),
+        };
+        (stream, sink)
+    }
+
+    #[tokio::test]
+    async fn test_stream_drop_oldest() {
+        let (mut stream, sink) = stream(LagPolicy::DropOldest).await;
+        for i in 0..4 {
+            sink.handle_notification(tip(i)).await.unwrap();
+        }
+
+        assert_eq!(height(stream.next().await), 2);
+        assert_eq!(height(stream.next().await), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stream_lagged() {
+        let (mut stream, sink) = stream(LagPolicy::Lagged).await;
+        for i in 0..5 {
+            sink.handle_notification(tip(i)).await.unwrap();
+        }
+
+        assert!(matches!(stream.next().await, Some(StreamItem::Lagged(3))));
+        assert_eq!(height(stream.next().await), 3);
+        assert_eq!(height(stream.next().await), 4);
+    }
+
+    #[tokio::test]
+    async fn test_stream_block_waits_for_consumer() {
+        let (mut stream, sink) = stream(LagPolicy::Block).await;
+        sink.handle_notification(tip(0)).await.unwrap();
+        sink.handle_notification(tip(1)).await.unwrap();
+
+        let producer = tokio::spawn(async move { sink.handle_notification(tip(2)).await });
+        tokio::task::yield_now().await;
+        assert!(!producer.is_finished());
+
+        assert_eq!(height(stream.next().await), 0);
+        producer.await.unwrap().unwrap();
+        assert_eq!(height(stream.next().await), 1);
+        assert_eq!(height(stream.next().await), 2);
+    }
+}